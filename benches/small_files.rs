@@ -0,0 +1,71 @@
+//! Throughput of decompressing an archive of many small files,
+//! streaming reader vs decompressing at once.
+//!
+//! `cargo bench --bench small_files`
+
+use std::io::{ self, Read, Write };
+use std::time::{ Duration, Instant };
+use zip::ZipWriter;
+use zip_parser::ZipArchive;
+use unzrip::ZipArchiveExt;
+
+const FILES: usize = 10000;
+const ROUNDS: u32 = 5;
+
+fn small_files_zip() -> anyhow::Result<(Vec<u8>, u64)> {
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    let mut total = 0;
+
+    for i in 0..FILES {
+        let line = format!("{}: the quick brown fox jumps over the lazy dog\n", i);
+        let data = line.repeat(i % 64 + 1);
+
+        writer.start_file(format!("{}.txt", i), Default::default())?;
+        writer.write_all(data.as_bytes())?;
+        total += data.len() as u64;
+    }
+
+    Ok((writer.finish()?.into_inner(), total))
+}
+
+fn bench(name: &str, total: u64, mut f: impl FnMut() -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let mut best = Duration::MAX;
+
+    for _ in 0..ROUNDS {
+        let now = Instant::now();
+        f()?;
+        best = best.min(now.elapsed());
+    }
+
+    let throughput = total as f64 / best.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{:<10} {:>10.2?} {:>10.1} MiB/s", name, best, throughput);
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let (buf, total) = small_files_zip()?;
+    let zip = ZipArchive::parse(&buf)?;
+    let entries = zip.entries()?.collect::<Result<Vec<_>, _>>()?;
+
+    println!("{} files, {} bytes", entries.len(), total);
+
+    bench("stream", total, || {
+        let mut output = Vec::new();
+        for cfh in &entries {
+            output.clear();
+            zip.reader(cfh)?.read_to_end(&mut output)?;
+        }
+        Ok(())
+    })?;
+
+    bench("at once", total, || {
+        for cfh in &entries {
+            let mut output = vec![0; cfh.uncomp_size as usize];
+            assert!(zip.inflate_into(cfh, &mut output)?);
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,331 @@
+//! Decryption of encrypted entries.
+//!
+//! https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT 6.1
+//! and https://www.winzip.com/en/support/aes-encryption/
+
+use std::{ fmt, io };
+use zip_parser::{ gp_flag, extra };
+use zip_parser::extra::ExtraFields;
+
+#[cfg(feature = "aes-crypto")]
+use {
+    anyhow::Context,
+    ctr::cipher::{ KeyIvInit, StreamCipher },
+    hmac::{ Hmac, Mac },
+    sha1::Sha1
+};
+
+/// The compression method of WinZip AES encrypted entry,
+/// the actual method is in the extra field.
+pub const AES_METHOD: u16 = 99;
+
+
+/// The password is wrong, or required but not given.
+#[derive(Debug)]
+pub struct InvalidPassword;
+
+impl fmt::Display for InvalidPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid password")
+    }
+}
+
+impl std::error::Error for InvalidPassword {}
+
+/// How an entry is encrypted, resolved from its header.
+pub struct Encryption {
+    kind: Kind,
+    method: u16,
+    check_crc: bool
+}
+
+enum Kind {
+    None,
+    ZipCrypto {
+        check: u8
+    },
+    Aes {
+        #[cfg_attr(not(feature = "aes-crypto"), allow(dead_code))]
+        strength: u8
+    }
+}
+
+impl Encryption {
+    pub fn new(gp_flag: u16, method: u16, crc32: u32, mod_time: u16, extra: ExtraFields<'_>)
+        -> anyhow::Result<Encryption>
+    {
+        if gp_flag & gp_flag::ENCRYPTED == 0 {
+            return Ok(Encryption { kind: Kind::None, method, check_crc: true });
+        }
+
+        if method != AES_METHOD {
+            let check = check_byte(gp_flag, crc32, mod_time);
+            return Ok(Encryption { kind: Kind::ZipCrypto { check }, method, check_crc: true });
+        }
+
+        let mut aes = None;
+        for field in extra {
+            let (id, data) = field?;
+            if id == extra::id::AES {
+                aes = Some(extra::Aes::parse(data)?);
+            }
+        }
+        let aes = aes.ok_or(zip_parser::Error::BadExtra)?;
+
+        Ok(Encryption {
+            kind: Kind::Aes { strength: aes.strength },
+            method: aes.method,
+            // AE-2 does not store crc, the authentication code is checked instead
+            check_crc: aes.version != 2
+        })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        !matches!(self.kind, Kind::None)
+    }
+
+    /// The compression method of the decrypted data.
+    pub fn method(&self) -> u16 {
+        self.method
+    }
+
+    /// Whether the crc of header should be checked.
+    pub fn check_crc(&self) -> bool {
+        self.check_crc
+    }
+
+    /// `data` is the encrypted data, including the encryption header.
+    pub fn decrypt<'a>(&self, data: &'a [u8], password: Option<&[u8]>)
+        -> anyhow::Result<Decryptor<'a>>
+    {
+        let password = match (&self.kind, password) {
+            (Kind::None, _) => return Ok(Decryptor::None(data)),
+            (_, Some(password)) => password,
+            (_, None) => return Err(anyhow::Error::new(InvalidPassword).context("password is required"))
+        };
+
+        match self.kind {
+            Kind::None => Ok(Decryptor::None(data)),
+            Kind::ZipCrypto { check } => {
+                let reader = ZipCrypto::new(data, password, check)?;
+                Ok(Decryptor::ZipCrypto(io::BufReader::new(reader)))
+            },
+            #[cfg(feature = "aes-crypto")]
+            Kind::Aes { strength } => {
+                let reader = WinZipAes::new(data, password, strength)?;
+                Ok(Decryptor::Aes(io::BufReader::new(reader)))
+            },
+            #[cfg(not(feature = "aes-crypto"))]
+            Kind::Aes { .. } => anyhow::bail!("aes encryption is not supported")
+        }
+    }
+}
+
+pub enum Decryptor<'a> {
+    None(&'a [u8]),
+    ZipCrypto(io::BufReader<ZipCrypto<&'a [u8]>>),
+    #[cfg(feature = "aes-crypto")]
+    Aes(io::BufReader<WinZipAes<'a>>)
+}
+
+impl io::Read for Decryptor<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decryptor::None(reader) => io::Read::read(reader, buf),
+            Decryptor::ZipCrypto(reader) => io::Read::read(reader, buf),
+            #[cfg(feature = "aes-crypto")]
+            Decryptor::Aes(reader) => io::Read::read(reader, buf)
+        }
+    }
+}
+
+impl io::BufRead for Decryptor<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Decryptor::None(reader) => reader.fill_buf(),
+            Decryptor::ZipCrypto(reader) => reader.fill_buf(),
+            #[cfg(feature = "aes-crypto")]
+            Decryptor::Aes(reader) => reader.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Decryptor::None(reader) => reader.consume(amt),
+            Decryptor::ZipCrypto(reader) => reader.consume(amt),
+            #[cfg(feature = "aes-crypto")]
+            Decryptor::Aes(reader) => reader.consume(amt)
+        }
+    }
+}
+
+/// The last byte of encryption header, used to check the password.
+///
+/// It is the high byte of crc32, or of mod time if the crc is deferred to a data descriptor.
+fn check_byte(gp_flag: u16, crc32: u32, mod_time: u16) -> u8 {
+    if gp_flag & gp_flag::DATA_DESCRIPTOR != 0 {
+        (mod_time >> 8) as u8
+    } else {
+        (crc32 >> 24) as u8
+    }
+}
+
+/// Traditional PKWARE encryption, the data starts with a 12-byte encryption header.
+pub struct ZipCrypto<R> {
+    reader: R,
+    keys: Keys
+}
+
+impl<R: io::Read> ZipCrypto<R> {
+    pub fn new(mut reader: R, password: &[u8], check: u8) -> anyhow::Result<ZipCrypto<R>> {
+        let mut keys = Keys::new(password);
+
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        keys.decrypt(&mut header);
+
+        if header[11] != check {
+            return Err(InvalidPassword.into());
+        }
+
+        Ok(ZipCrypto { reader, keys })
+    }
+}
+
+impl<R: io::Read> io::Read for ZipCrypto<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.keys.decrypt(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+struct Keys([u32; 3]);
+
+impl Keys {
+    fn new(password: &[u8]) -> Keys {
+        let mut keys = Keys([0x12345678, 0x23456789, 0x34567890]);
+        for &c in password {
+            keys.update(c);
+        }
+        keys
+    }
+
+    fn update(&mut self, c: u8) {
+        let [key0, key1, key2] = &mut self.0;
+
+        *key0 = crc32_update(*key0, c);
+        *key1 = key1.wrapping_add(*key0 & 0xff)
+            .wrapping_mul(134775813)
+            .wrapping_add(1);
+        *key2 = crc32_update(*key2, (*key1 >> 24) as u8);
+    }
+
+    fn decrypt(&mut self, buf: &mut [u8]) {
+        for c in buf.iter_mut() {
+            let temp = (self.0[2] | 2) as u16;
+            *c ^= (temp.wrapping_mul(temp ^ 1) >> 8) as u8;
+            self.update(*c);
+        }
+    }
+}
+
+fn crc32_update(crc: u32, c: u8) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut j = 0;
+            while j < 8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+                j += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    (crc >> 8) ^ TABLE[((crc ^ u32::from(c)) & 0xff) as usize]
+}
+
+/// WinZip AES encryption, the data is salt, password verification value,
+/// AES-CTR encrypted data and HMAC-SHA1 authentication code.
+///
+/// The authentication code is checked before decryption,
+/// as the decoder may not read the data to the end.
+#[cfg(feature = "aes-crypto")]
+pub struct WinZipAes<'a> {
+    data: &'a [u8],
+    cipher: Box<dyn StreamCipher>
+}
+
+#[cfg(feature = "aes-crypto")]
+const AES_ITERATIONS: u32 = 1000;
+#[cfg(feature = "aes-crypto")]
+const AES_VERIFIER_LEN: usize = 2;
+#[cfg(feature = "aes-crypto")]
+const AES_MAC_LEN: usize = 10;
+
+#[cfg(feature = "aes-crypto")]
+impl WinZipAes<'_> {
+    pub fn new<'a>(data: &'a [u8], password: &[u8], strength: u8) -> anyhow::Result<WinZipAes<'a>> {
+        type Aes128Ctr = ctr::Ctr128LE<aes::Aes128>;
+        type Aes192Ctr = ctr::Ctr128LE<aes::Aes192>;
+        type Aes256Ctr = ctr::Ctr128LE<aes::Aes256>;
+
+        let key_len = match strength {
+            1 => 16,
+            2 => 24,
+            3 => 32,
+            _ => anyhow::bail!("aes strength is not supported: {}", strength)
+        };
+        let salt_len = key_len / 2;
+
+        let data_len = data.len()
+            .checked_sub(salt_len + AES_VERIFIER_LEN + AES_MAC_LEN)
+            .context("aes encrypted data is too short")?;
+        let (salt, data) = data.split_at(salt_len);
+        let (verifier, data) = data.split_at(AES_VERIFIER_LEN);
+        let (data, code) = data.split_at(data_len);
+
+        let mut key = [0; 32 + 32 + AES_VERIFIER_LEN];
+        let key = &mut key[..key_len * 2 + AES_VERIFIER_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, AES_ITERATIONS, key);
+        let (enc_key, key) = key.split_at(key_len);
+        let (mac_key, expect_verifier) = key.split_at(key_len);
+
+        if verifier != expect_verifier {
+            return Err(InvalidPassword.into());
+        }
+
+        let mut mac = Hmac::<Sha1>::new_from_slice(mac_key)
+            .expect("hmac accepts key of any length");
+        mac.update(data);
+        if mac.verify_truncated_left(code).is_err() {
+            anyhow::bail!("aes authentication code check failed");
+        }
+
+        // little endian counter, starting from 1
+        let mut nonce = [0; 16];
+        nonce[0] = 1;
+        let nonce = (&nonce).into();
+        let cipher: Box<dyn StreamCipher> = match key_len {
+            16 => Box::new(Aes128Ctr::new(enc_key.into(), nonce)),
+            24 => Box::new(Aes192Ctr::new(enc_key.into(), nonce)),
+            _ => Box::new(Aes256Ctr::new(enc_key.into(), nonce))
+        };
+
+        Ok(WinZipAes { data, cipher })
+    }
+}
+
+#[cfg(feature = "aes-crypto")]
+impl io::Read for WinZipAes<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = io::Read::read(&mut self.data, buf)?;
+        self.cipher.apply_keystream(&mut buf[..n]);
+        Ok(n)
+    }
+}
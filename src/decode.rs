@@ -0,0 +1,513 @@
+use std::io;
+#[cfg(any(feature = "lzma", feature = "legacy-methods"))]
+use anyhow::Context;
+use flate2::bufread::DeflateDecoder;
+use zip_parser::compress;
+
+#[cfg(feature = "deflate64")]
+use deflate64::Deflate64Decoder;
+
+#[cfg(all(feature = "zstd-sys", feature = "zstd-rust"))]
+compile_error!("`zstd-sys` and `zstd-rust` features are mutually exclusive");
+
+#[cfg(feature = "zstd-sys")]
+type ZstdDecoder<R> = zstd::stream::read::Decoder<'static, R>;
+
+#[cfg(all(feature = "zstd-rust", not(feature = "zstd-sys")))]
+type ZstdDecoder<R> = RuzstdDecoder<R>;
+
+#[cfg(feature = "bzip2")]
+use bzip2::bufread::BzDecoder;
+
+#[cfg(feature = "lzma")]
+use xz2::stream::{ Action, Status, Stream as XzStream };
+
+
+pub enum Decoder<R: io::BufRead> {
+    None(R),
+    Deflate(DeflateDecoder<R>),
+    #[cfg(feature = "deflate64")]
+    Deflate64(Deflate64Decoder<R>),
+    #[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+    Zstd(ZstdDecoder<R>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(BzDecoder<R>),
+    #[cfg(feature = "lzma")]
+    Lzma(XzDecoder<io::Chain<io::Cursor<[u8; 13]>, R>>),
+    #[cfg(feature = "lzma")]
+    Xz(XzDecoder<R>),
+    #[cfg(feature = "legacy-methods")]
+    Legacy(io::Cursor<Vec<u8>>)
+}
+
+/// Limits of the decoders.
+#[derive(Debug, Clone, Copy)]
+pub struct DecoderOptions {
+    #[cfg_attr(not(any(feature = "zstd-sys", feature = "zstd-rust")), allow(dead_code))]
+    zstd_window_log_max: u32
+}
+
+impl Default for DecoderOptions {
+    fn default() -> DecoderOptions {
+        DecoderOptions { zstd_window_log_max: DEFAULT_ZSTD_WINDOW_LOG_MAX }
+    }
+}
+
+impl DecoderOptions {
+    /// Limit the window size of zstd frames to `1 << log` bytes,
+    /// a frame with larger window is rejected instead of being allocated.
+    pub fn zstd_window_log_max(mut self, log: u32) -> anyhow::Result<DecoderOptions> {
+        if !(10..=31).contains(&log) {
+            anyhow::bail!("zstd window log must be between 10 and 31: {}", log);
+        }
+
+        self.zstd_window_log_max = log;
+        Ok(self)
+    }
+}
+
+impl<R: io::BufRead> Decoder<R> {
+    /// `uncomp_size` is `None` if it is deferred to a data descriptor.
+    pub fn new(method: u16, gp_flag: u16, uncomp_size: Option<u64>, reader: R)
+        -> anyhow::Result<Decoder<R>>
+    {
+        Decoder::with_options(method, gp_flag, uncomp_size, reader, &DecoderOptions::default())
+    }
+
+    /// Like [`Decoder::new`], but with the given limits.
+    #[cfg_attr(
+        not(all(
+            any(feature = "lzma", feature = "legacy-methods"),
+            any(feature = "zstd-sys", feature = "zstd-rust")
+        )),
+        allow(unused_variables)
+    )]
+    pub fn with_options(method: u16, gp_flag: u16, uncomp_size: Option<u64>, reader: R, options: &DecoderOptions)
+        -> anyhow::Result<Decoder<R>>
+    {
+        Ok(match method {
+            compress::STORE => Decoder::None(reader),
+            compress::DEFLATE => Decoder::Deflate(DeflateDecoder::new(reader)),
+            #[cfg(feature = "deflate64")]
+            compress::DEFLATE64 => Decoder::Deflate64(Deflate64Decoder::with_buffer(reader)),
+            #[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+            compress::ZSTD => Decoder::Zstd(zstd_decoder(reader, options.zstd_window_log_max)?),
+            #[cfg(feature = "bzip2")]
+            compress::BZIP2 => Decoder::Bzip2(BzDecoder::new(reader)),
+            #[cfg(feature = "lzma")]
+            compress::LZMA => Decoder::Lzma(lzma_decoder(reader, gp_flag, uncomp_size)?),
+            #[cfg(feature = "lzma")]
+            compress::XZ => {
+                let stream = XzStream::new_stream_decoder(LZMA_MEMLIMIT, 0)?;
+                Decoder::Xz(XzDecoder::new(reader, stream))
+            },
+            #[cfg(feature = "legacy-methods")]
+            compress::SHRINK ..= compress::IMPLODE => Decoder::Legacy(legacy_decode(method, gp_flag, uncomp_size, reader)?),
+            _ => anyhow::bail!("compress method is not supported: {}", method)
+        })
+    }
+
+    /// Stop at the end of the first frame,
+    /// required if the compressed size is unknown.
+    pub fn single_frame(self) -> Decoder<R> {
+        match self {
+            #[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+            Decoder::Zstd(reader) => Decoder::Zstd(reader.single_frame()),
+            decoder => decoder
+        }
+    }
+
+    /// The compressed input, `None` if it has been read to the end at once.
+    pub fn get_ref(&self) -> Option<&R> {
+        match self {
+            Decoder::None(reader) => Some(reader),
+            Decoder::Deflate(reader) => Some(reader.get_ref()),
+            #[cfg(feature = "deflate64")]
+            Decoder::Deflate64(reader) => Some(reader.get_ref()),
+            #[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+            Decoder::Zstd(reader) => Some(reader.get_ref()),
+            #[cfg(feature = "bzip2")]
+            Decoder::Bzip2(reader) => Some(reader.get_ref()),
+            #[cfg(feature = "lzma")]
+            Decoder::Lzma(reader) => Some(reader.get_ref().get_ref().1),
+            #[cfg(feature = "lzma")]
+            Decoder::Xz(reader) => Some(reader.get_ref()),
+            #[cfg(feature = "legacy-methods")]
+            Decoder::Legacy(_) => None
+        }
+    }
+}
+
+impl<R: io::BufRead> io::Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::None(reader) => io::Read::read(reader, buf),
+            Decoder::Deflate(reader) => io::Read::read(reader, buf),
+            #[cfg(feature = "deflate64")]
+            Decoder::Deflate64(reader) => io::Read::read(reader, buf),
+            #[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+            Decoder::Zstd(reader) => io::Read::read(reader, buf),
+            #[cfg(feature = "bzip2")]
+            Decoder::Bzip2(reader) => io::Read::read(reader, buf),
+            #[cfg(feature = "lzma")]
+            Decoder::Lzma(reader) => io::Read::read(reader, buf),
+            #[cfg(feature = "lzma")]
+            Decoder::Xz(reader) => io::Read::read(reader, buf),
+            #[cfg(feature = "legacy-methods")]
+            Decoder::Legacy(reader) => io::Read::read(reader, buf)
+        }
+    }
+}
+
+/// Memory limit of lzma and xz decoders,
+/// the dictionary size comes from the archive and may be up to 4 GiB.
+#[cfg(feature = "lzma")]
+const LZMA_MEMLIMIT: u64 = 1 << 30;
+
+#[cfg(feature = "lzma")]
+fn lzma_error(err: xz2::stream::Error) -> io::Error {
+    match err {
+        xz2::stream::Error::MemLimit => io::Error::other(format!(
+            "lzma dictionary exceeds the memory limit {} MiB",
+            LZMA_MEMLIMIT >> 20
+        )),
+        err => err.into()
+    }
+}
+
+/// Lzma and xz decoder, it stops at the end of stream
+/// and leaves the following data unread, such as the data descriptor.
+#[cfg(feature = "lzma")]
+pub struct XzDecoder<R> {
+    reader: R,
+    stream: XzStream,
+    finished: bool
+}
+
+#[cfg(feature = "lzma")]
+impl<R: io::BufRead> XzDecoder<R> {
+    fn new(reader: R, stream: XzStream) -> XzDecoder<R> {
+        XzDecoder { reader, stream, finished: false }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+}
+
+#[cfg(feature = "lzma")]
+impl<R: io::BufRead> io::Read for XzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.finished && !buf.is_empty() {
+            let input = self.reader.fill_buf()?;
+            let eof = input.is_empty();
+            let action = if eof { Action::Finish } else { Action::Run };
+
+            let (total_in, total_out) = (self.stream.total_in(), self.stream.total_out());
+            let status = self.stream.process(input, buf, action).map_err(lzma_error)?;
+            let consumed = (self.stream.total_in() - total_in) as usize;
+            let read = (self.stream.total_out() - total_out) as usize;
+            self.reader.consume(consumed);
+
+            self.finished = status == Status::StreamEnd;
+
+            if read != 0 || self.finished {
+                return Ok(read);
+            } else if eof {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "lzma stream is truncated"));
+            } else if consumed == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "lzma stream is stuck"));
+            }
+        }
+
+        Ok(0)
+    }
+}
+
+/// Zip lzma data starts with version and properties,
+/// convert it to the header of `.lzma` file, which is properties and uncompressed size.
+#[cfg(feature = "lzma")]
+fn lzma_decoder<R: io::BufRead>(mut reader: R, gp_flag: u16, uncomp_size: Option<u64>)
+    -> anyhow::Result<XzDecoder<io::Chain<io::Cursor<[u8; 13]>, R>>>
+{
+    let mut buf = [0; 4];
+    io::Read::read_exact(&mut reader, &mut buf)?;
+    let props_len = u16::from_le_bytes([buf[2], buf[3]]);
+    if props_len != 5 {
+        anyhow::bail!("lzma properties size is not supported: {}", props_len);
+    }
+
+    let mut header = [0; 13];
+    io::Read::read_exact(&mut reader, &mut header[..5])?;
+
+    // unknown size, the decoder stops at the end of stream marker
+    let size = if gp_flag & zip_parser::gp_flag::LZMA_EOS != 0 {
+        u64::MAX
+    } else {
+        uncomp_size.context("lzma data without end of stream marker requires the uncompressed size")?
+    };
+    header[5..].copy_from_slice(&size.to_le_bytes());
+
+    let stream = XzStream::new_lzma_decoder(LZMA_MEMLIMIT)?;
+    Ok(XzDecoder::new(io::Read::chain(io::Cursor::new(header), reader), stream))
+}
+
+/// The default of `--zstd-window-log-max`, the same limit as the zstd command.
+pub const DEFAULT_ZSTD_WINDOW_LOG_MAX: u32 = 27;
+
+#[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+const ZSTD_MAGIC: u32 = 0xfd2fb528;
+
+#[cfg(feature = "zstd-sys")]
+fn zstd_decoder<R: io::BufRead>(mut reader: R, log: u32) -> io::Result<ZstdDecoder<R>> {
+    // libzstd checks every frame, this only gives a better message
+    if let Some(size) = zstd_window_size(reader.fill_buf()?) {
+        check_zstd_window(size, log)?;
+    }
+
+    let mut decoder = ZstdDecoder::with_buffer(reader)?;
+    decoder.window_log_max(log)?;
+
+    Ok(decoder)
+}
+
+#[cfg(all(feature = "zstd-rust", not(feature = "zstd-sys")))]
+fn zstd_decoder<R: io::BufRead>(reader: R, log: u32) -> io::Result<ZstdDecoder<R>> {
+    RuzstdDecoder::new(reader, log)
+}
+
+#[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+fn check_zstd_window(size: u64, log: u32) -> io::Result<()> {
+    if size > 1 << log {
+        let msg = format!("zstd window size {} exceeds the limit {}, raise it with --zstd-window-log-max",
+            size,
+            1u64 << log
+        );
+        Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+    } else {
+        Ok(())
+    }
+}
+
+/// Read the whole zstd frame header and check its window size,
+/// the header is returned to be read by the decoder again.
+///
+/// Only the magic number is read if it is not a zstd frame.
+#[cfg(feature = "zstd-rust")]
+fn read_zstd_header<R: io::Read>(reader: &mut R, log: u32) -> io::Result<([u8; 18], usize)> {
+    let mut header = [0; 18];
+    reader.read_exact(&mut header[..4])?;
+    if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) != ZSTD_MAGIC {
+        return Ok((header, 4));
+    }
+
+    reader.read_exact(&mut header[4..5])?;
+    let descriptor = header[4];
+    let single_segment = descriptor & (1 << 5) != 0;
+    let window_len = if single_segment { 0 } else { 1 };
+    let dict_id_len = [0, 1, 2, 4][usize::from(descriptor & 0x3)];
+    let size_len = match descriptor >> 6 {
+        0 if single_segment => 1,
+        flag => [0, 2, 4, 8][usize::from(flag)]
+    };
+
+    let len = 5 + window_len + dict_id_len + size_len;
+    reader.read_exact(&mut header[5..len])
+        .map_err(|err| io::Error::new(err.kind(), "zstd frame header is truncated"))?;
+
+    let size = zstd_window_size(&header[..len])
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad zstd frame header"))?;
+    check_zstd_window(size, log)?;
+
+    Ok((header, len))
+}
+
+/// Parse the window size from zstd frame header,
+/// `None` if it is not a zstd frame or the header is incomplete.
+#[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+fn zstd_window_size(buf: &[u8]) -> Option<u64> {
+    let (magic, buf) = buf.split_first_chunk::<4>()?;
+    if u32::from_le_bytes(*magic) != ZSTD_MAGIC {
+        return None;
+    }
+
+    let (&descriptor, buf) = buf.split_first()?;
+    let single_segment = descriptor & (1 << 5) != 0;
+
+    if !single_segment {
+        let window_descriptor = *buf.first()?;
+        let base = 1u64 << (10 + (window_descriptor >> 3));
+        return Some(base + base / 8 * u64::from(window_descriptor & 0x7));
+    }
+
+    // the window size of single segment frame is its content size
+    let dict_id_len = [0, 1, 2, 4][usize::from(descriptor & 0x3)];
+    let size_len = [1, 2, 4, 8][usize::from(descriptor >> 6)];
+    let size = buf.get(dict_id_len..)?.get(..size_len)?;
+
+    let mut bytes = [0; 8];
+    bytes[..size_len].copy_from_slice(size);
+    let size = u64::from_le_bytes(bytes);
+
+    Some(if size_len == 2 { size + 256 } else { size })
+}
+
+/// Pure rust zstd decoder, it reads like `zstd::stream::read::Decoder`.
+///
+/// The window size of every frame is checked before it is allocated.
+#[cfg(feature = "zstd-rust")]
+pub struct RuzstdDecoder<R> {
+    reader: R,
+    decoder: Box<ruzstd::decoding::FrameDecoder>,
+    single_frame: bool,
+    window_log_max: u32
+}
+
+#[cfg(feature = "zstd-rust")]
+impl<R: io::BufRead> RuzstdDecoder<R> {
+    /// Limit the window size of frames to `1 << window_log_max` bytes.
+    pub fn new(reader: R, window_log_max: u32) -> io::Result<RuzstdDecoder<R>> {
+        let mut decoder = RuzstdDecoder {
+            reader,
+            decoder: Box::new(ruzstd::decoding::FrameDecoder::new()),
+            single_frame: false,
+            window_log_max
+        };
+        decoder.next_frame()?;
+        Ok(decoder)
+    }
+
+    fn next_frame(&mut self) -> io::Result<()> {
+        let (header, len) = read_zstd_header(&mut self.reader, self.window_log_max)?;
+        let source = io::Read::chain(&header[..len], &mut self.reader);
+        self.decoder.reset(source).map_err(io::Error::other)
+    }
+
+    pub fn single_frame(mut self) -> RuzstdDecoder<R> {
+        self.single_frame = true;
+        self
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+}
+
+#[cfg(feature = "zstd-rust")]
+impl<R: io::BufRead> io::Read for RuzstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use ruzstd::decoding::BlockDecodingStrategy;
+
+        loop {
+            while self.decoder.can_collect() < buf.len() && !self.decoder.is_finished() {
+                let n = buf.len() - self.decoder.can_collect();
+                self.decoder.decode_blocks(&mut self.reader, BlockDecodingStrategy::UptoBytes(n))
+                    .map_err(io::Error::other)?;
+            }
+
+            let n = self.decoder.read(buf)?;
+            if n != 0 || buf.is_empty() || self.single_frame || self.reader.fill_buf()?.is_empty() {
+                return Ok(n);
+            }
+
+            // the next frame
+            self.next_frame()?;
+        }
+    }
+}
+
+#[cfg(feature = "legacy-methods")]
+fn legacy_decode<R: io::BufRead>(method: u16, gp_flag: u16, uncomp_size: Option<u64>, mut reader: R)
+    -> anyhow::Result<io::Cursor<Vec<u8>>>
+{
+    let size = uncomp_size.context("legacy compress method requires the uncompressed size")?;
+    let size = usize::try_from(size)?;
+
+    let mut input = Vec::new();
+    io::Read::read_to_end(&mut reader, &mut input)?;
+    let output = crate::legacy::decode(method, gp_flag, &input, size)?;
+
+    Ok(io::Cursor::new(output))
+}
+
+/// Decode raw deflate data at once, the output must be filled exactly.
+pub fn inflate(input: &[u8], output: &mut [u8]) -> io::Result<()> {
+    let n = miniz_oxide::inflate::decompress_slice_iter_to_slice(output, std::iter::once(input), false, false)
+        .map_err(|status| {
+            let msg = format!("inflate failed: {:?}", status);
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        })?;
+
+    if n != output.len() {
+        let msg = format!("inflated size does not match. expect: {}, got: {}", output.len(), n);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+
+    Ok(())
+}
+
+pub struct Crc32Checker<R> {
+    reader: R,
+    expect: Option<u32>,
+    hasher: crc32fast::Hasher,
+    amount: u64
+}
+
+impl<R> Crc32Checker<R> {
+    pub fn new(reader: R, expect: u32) -> Crc32Checker<R> {
+        Crc32Checker {
+            reader,
+            expect: Some(expect),
+            hasher: crc32fast::Hasher::new(),
+            amount: 0
+        }
+    }
+
+    /// The crc is not known yet, the caller should check `crc32` at the end.
+    pub fn deferred(reader: R) -> Crc32Checker<R> {
+        Crc32Checker {
+            reader,
+            expect: None,
+            hasher: crc32fast::Hasher::new(),
+            amount: 0
+        }
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+
+    /// The number of bytes read so far.
+    pub fn amount(&self) -> u64 {
+        self.amount
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+}
+
+impl<R: io::Read> io::Read for Crc32Checker<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = io::Read::read(&mut self.reader, buf)?;
+
+        if n == 0 {
+            let crc = self.crc32();
+            match self.expect {
+                Some(expect) if crc != expect => {
+                    let msg = format!("crc32 check failed. expect: {}, got: {}",
+                        expect,
+                        crc
+                    );
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+                },
+                _ => ()
+            }
+        } else {
+            self.hasher.update(&buf[..n]);
+            self.amount += n as u64;
+        }
+
+        Ok(n)
+    }
+}
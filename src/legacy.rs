@@ -0,0 +1,356 @@
+//! Decoders of the legacy compression methods, Shrink, Reduce and Implode.
+//!
+//! https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT 5.1 - 5.3
+//!
+//! These methods are only found in old and small archives,
+//! so the whole entry is decoded at once.
+
+use std::io;
+use zip_parser::compress;
+
+
+/// Implode uses 8K sliding dictionary instead of 4K.
+const IMPLODE_8K_DICT: u16 = 1 << 1;
+
+/// Implode encodes literals with a Shannon-Fano tree.
+const IMPLODE_LITERAL_TREE: u16 = 1 << 2;
+
+pub fn decode(method: u16, gp_flag: u16, input: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    match method {
+        compress::SHRINK => unshrink(input, size),
+        compress::REDUCE1 ..= compress::REDUCE4 => unreduce(input, size, (method - 1) as u32),
+        compress::IMPLODE => explode(input, size, gp_flag),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "not a legacy compress method"))
+    }
+}
+
+/// Dynamic LZW with 9 to 13 bits codes and partial clearing.
+pub fn unshrink(input: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    const MIN_CODE_SIZE: u32 = 9;
+    const MAX_CODE_SIZE: u32 = 13;
+    const TABLE_SIZE: usize = 1 << MAX_CODE_SIZE;
+    const CONTROL: u16 = 256;
+    const FREE: u16 = u16::MAX;
+
+    fn first_byte(parent: &[u16], value: &[u8], mut code: usize) -> io::Result<u8> {
+        for _ in 0..TABLE_SIZE {
+            match parent[code] {
+                FREE => break,
+                CONTROL => return Ok(value[code]),
+                p => code = usize::from(p)
+            }
+        }
+
+        Err(invalid("invalid shrink code"))
+    }
+
+    // the string of code is the string of its parent followed by its value
+    let mut parent = vec![FREE; TABLE_SIZE];
+    let mut value = vec![0; TABLE_SIZE];
+    for code in 0..256 {
+        parent[code] = CONTROL;
+        value[code] = code as u8;
+    }
+
+    // codes to be added, in order
+    let mut free: Vec<usize> = (usize::from(CONTROL) + 1..TABLE_SIZE).rev().collect();
+
+    let mut bits = BitReader::new(input);
+    let mut output = Vec::new();
+    let mut string = Vec::new();
+    let mut code_size = MIN_CODE_SIZE;
+    let mut prev = None;
+
+    while output.len() < size {
+        let code = bits.read(code_size)? as usize;
+
+        if code == usize::from(CONTROL) {
+            match bits.read(code_size)? {
+                1 if code_size < MAX_CODE_SIZE => code_size += 1,
+                2 => {
+                    // remove all codes that are not the parent of other code,
+                    // a removed code that is still a parent will never be added again.
+                    let mut is_parent = vec![false; TABLE_SIZE];
+                    for &p in &parent[usize::from(CONTROL) + 1..] {
+                        if p != FREE {
+                            is_parent[usize::from(p)] = true;
+                        }
+                    }
+                    free.clear();
+                    for code in (usize::from(CONTROL) + 1..TABLE_SIZE).rev() {
+                        if !is_parent[code] {
+                            parent[code] = FREE;
+                            free.push(code);
+                        }
+                    }
+                },
+                _ => return Err(invalid("invalid shrink control code"))
+            }
+            continue;
+        }
+
+        let prev_code = match prev {
+            Some(prev_code) => prev_code,
+            None => {
+                let c = u8::try_from(code).map_err(|_| invalid("invalid shrink code"))?;
+                output.push(c);
+                prev = Some(code);
+                continue;
+            }
+        };
+
+        string.clear();
+        let mut next = code;
+        while next != usize::from(CONTROL) {
+            if string.len() >= TABLE_SIZE {
+                return Err(invalid("invalid shrink code"));
+            }
+
+            if parent[next] == FREE {
+                // the code is used before being added, its string is the previous one
+                // followed by the first byte of the previous one.
+                if free.last() != Some(&next) {
+                    return Err(invalid("invalid shrink code"));
+                }
+                string.push(first_byte(&parent, &value, prev_code)?);
+                next = prev_code;
+            } else {
+                string.push(value[next]);
+                next = usize::from(parent[next]);
+            }
+        }
+        string.reverse();
+
+        let first = string[0];
+        output.extend_from_slice(&string);
+
+        if let Some(code) = free.pop() {
+            parent[code] = prev_code as u16;
+            value[code] = first;
+        }
+        prev = Some(code);
+    }
+
+    output.truncate(size);
+    Ok(output)
+}
+
+/// Probabilistic compression with follower sets, then run length expanding.
+///
+/// `factor` is 1 to 4, the number of bits of distance taken from the length byte.
+pub fn unreduce(input: &[u8], size: usize, factor: u32) -> io::Result<Vec<u8>> {
+    const DLE: u8 = 144;
+
+    enum State {
+        Literal,
+        Escape,
+        Length { value: u8, len: usize },
+        Distance { value: u8, len: usize }
+    }
+
+    let mut bits = BitReader::new(input);
+
+    let mut followers = vec![Vec::new(); 256];
+    for set in followers.iter_mut().rev() {
+        let len = bits.read(6)?;
+        for _ in 0..len {
+            set.push(bits.read(8)? as u8);
+        }
+    }
+
+    let len_mask = 0xff >> factor;
+    let mut output = Vec::new();
+    let mut state = State::Literal;
+    let mut last = 0;
+
+    while output.len() < size {
+        let set: &Vec<u8> = &followers[usize::from(last)];
+        let c = if set.is_empty() || bits.read(1)? == 1 {
+            bits.read(8)? as u8
+        } else {
+            // minimal bits of indexes, at least 1
+            let n = (usize::BITS - (set.len() - 1).leading_zeros()).max(1);
+            let index = bits.read(n)? as usize;
+            *set.get(index).ok_or_else(|| invalid("invalid reduce follower index"))?
+        };
+        last = c;
+
+        state = match state {
+            State::Literal if c == DLE => State::Escape,
+            State::Literal => {
+                output.push(c);
+                State::Literal
+            },
+            State::Escape if c == 0 => {
+                output.push(DLE);
+                State::Literal
+            },
+            State::Escape => {
+                let len = usize::from(c & len_mask);
+                if len == usize::from(len_mask) {
+                    State::Length { value: c, len }
+                } else {
+                    State::Distance { value: c, len }
+                }
+            },
+            State::Length { value, len } => State::Distance { value, len: len + usize::from(c) },
+            State::Distance { value, len } => {
+                let dist = (usize::from(value >> (8 - factor)) << 8) + usize::from(c) + 1;
+                copy_match(&mut output, dist, len + 3, size);
+                State::Literal
+            }
+        };
+    }
+
+    Ok(output)
+}
+
+/// Sliding dictionary with Shannon-Fano coded lengths, distances and optionally literals.
+pub fn explode(input: &[u8], size: usize, gp_flag: u16) -> io::Result<Vec<u8>> {
+    let dist_low_bits = if gp_flag & IMPLODE_8K_DICT != 0 { 7 } else { 6 };
+    let (min_len, literals) = if gp_flag & IMPLODE_LITERAL_TREE != 0 { (3, true) } else { (2, false) };
+
+    let mut bits = BitReader::new(input);
+
+    let literals = if literals {
+        Some(ShannonFano::read(&mut bits, 256)?)
+    } else {
+        None
+    };
+    let lengths = ShannonFano::read(&mut bits, 64)?;
+    let distances = ShannonFano::read(&mut bits, 64)?;
+
+    let mut output = Vec::new();
+
+    while output.len() < size {
+        if bits.read(1)? == 1 {
+            let c = match literals.as_ref() {
+                Some(tree) => tree.decode(&mut bits)?,
+                None => bits.read(8)? as u16
+            };
+            output.push(c as u8);
+        } else {
+            let low = bits.read(dist_low_bits)? as usize;
+            let high = usize::from(distances.decode(&mut bits)?);
+            let dist = ((high << dist_low_bits) | low) + 1;
+
+            let mut len = usize::from(lengths.decode(&mut bits)?);
+            if len == 63 {
+                len += bits.read(8)? as usize;
+            }
+
+            copy_match(&mut output, dist, len + min_len, size);
+        }
+    }
+
+    Ok(output)
+}
+
+/// Copy `len` bytes from `dist` bytes back, the bytes before the start are zeros.
+fn copy_match(output: &mut Vec<u8>, dist: usize, len: usize, size: usize) {
+    let len = len.min(size - output.len());
+
+    for _ in 0..len {
+        let c = output.len()
+            .checked_sub(dist)
+            .map(|pos| output[pos])
+            .unwrap_or(0);
+        output.push(c);
+    }
+}
+
+/// The codes are canonical huffman codes with all bits inverted.
+struct ShannonFano {
+    /// number of codes of each bit length
+    counts: [u16; 17],
+    /// symbols ordered by bit length
+    symbols: Vec<u16>
+}
+
+impl ShannonFano {
+    fn read(bits: &mut BitReader<'_>, n: usize) -> io::Result<ShannonFano> {
+        let mut lens = Vec::with_capacity(n);
+
+        // each byte is the number of codes and their bit length
+        let len = bits.read(8)? + 1;
+        for _ in 0..len {
+            let byte = bits.read(8)?;
+            let count = (byte >> 4) + 1;
+            let bit_len = (byte & 0xf) + 1;
+            for _ in 0..count {
+                lens.push(bit_len as usize);
+            }
+        }
+
+        if lens.len() != n {
+            return Err(invalid("invalid implode tree"));
+        }
+
+        let mut counts = [0; 17];
+        for &bit_len in &lens {
+            counts[bit_len] += 1;
+        }
+
+        let mut symbols = Vec::with_capacity(n);
+        for bit_len in 1..counts.len() {
+            for (symbol, _) in lens.iter().enumerate().filter(|(_, &len)| len == bit_len) {
+                symbols.push(symbol as u16);
+            }
+        }
+
+        Ok(ShannonFano { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut BitReader<'_>) -> io::Result<u16> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+
+        for &count in &self.counts[1..] {
+            code |= (bits.read(1)? ^ 1) as usize;
+            let count = usize::from(count);
+
+            if code < first + count {
+                return Ok(self.symbols[index + code - first]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("invalid implode code"))
+    }
+}
+
+/// Read bits from the least significant bit of each byte.
+struct BitReader<'a> {
+    input: &'a [u8],
+    pos: usize
+}
+
+impl BitReader<'_> {
+    fn new(input: &[u8]) -> BitReader<'_> {
+        BitReader { input, pos: 0 }
+    }
+
+    fn read(&mut self, n: u32) -> io::Result<u32> {
+        let end = self.pos + n as usize;
+        if end > self.input.len() * 8 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut value = 0;
+        for (i, pos) in (self.pos..end).enumerate() {
+            let bit = (self.input[pos / 8] >> (pos % 8)) & 1;
+            value |= u32::from(bit) << i;
+        }
+        self.pos = end;
+
+        Ok(value)
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
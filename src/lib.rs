@@ -0,0 +1,141 @@
+//! Decompressing and crc checked reading of zip entries,
+//! the same as the `unzrip` command does.
+//!
+//! ```no_run
+//! use std::io::Read;
+//! use zip_parser::ZipArchive;
+//! use unzrip::ZipArchiveExt;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let buf = std::fs::read("example.zip")?;
+//! let zip = ZipArchive::parse(&buf)?;
+//!
+//! for cfh in zip.entries()? {
+//!     let cfh = cfh?;
+//!     let mut data = Vec::new();
+//!     zip.reader(&cfh)?.read_to_end(&mut data)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod decode;
+pub mod crypto;
+#[cfg(feature = "legacy-methods")]
+pub mod legacy;
+
+pub use decode::{ Decoder, DecoderOptions, Crc32Checker, inflate };
+#[cfg(feature = "lzma")]
+pub use decode::XzDecoder;
+#[cfg(feature = "zstd-rust")]
+pub use decode::RuzstdDecoder;
+
+use std::io::{ self, Read };
+use zip_parser::{ compress, gp_flag, ZipArchive, CentralFileHeader };
+use crypto::{ Encryption, Decryptor };
+
+
+/// The uncompressed data of an entry,
+/// limited to the recorded size and checked against the recorded crc at the end.
+pub type EntryReader<'a> = Crc32Checker<io::Take<Decoder<Decryptor<'a>>>>;
+
+pub trait ZipArchiveExt {
+    /// Read the local file header of the entry and return the reader of its data.
+    ///
+    /// The crc is checked when the reader reaches the end,
+    /// so the data must be read to the end to be trusted.
+    fn reader<'a>(&'a self, cfh: &CentralFileHeader<'_>) -> anyhow::Result<EntryReader<'a>> {
+        self.reader_with_password(cfh, None)
+    }
+
+    /// Like [`ZipArchiveExt::reader`], but also decrypt the encrypted entry.
+    ///
+    /// Fails with [`crypto::InvalidPassword`] if the password is wrong or missing.
+    fn reader_with_password<'a>(&'a self, cfh: &CentralFileHeader<'_>, password: Option<&[u8]>)
+        -> anyhow::Result<EntryReader<'a>>
+    {
+        self.reader_with_options(cfh, password, &DecoderOptions::default())
+    }
+
+    /// Like [`ZipArchiveExt::reader_with_password`], but with the given decoder limits.
+    fn reader_with_options<'a>(
+        &'a self,
+        cfh: &CentralFileHeader<'_>,
+        password: Option<&[u8]>,
+        options: &DecoderOptions
+    ) -> anyhow::Result<EntryReader<'a>>;
+
+    /// Decompress a deflate entry at once into `output`, which is exactly `cfh.uncomp_size` bytes.
+    ///
+    /// This is faster than the reader for small entries.
+    /// Returns `false` if the entry is not an unencrypted deflate entry, use the reader instead.
+    fn inflate_into(&self, cfh: &CentralFileHeader<'_>, output: &mut [u8]) -> anyhow::Result<bool>;
+}
+
+impl ZipArchiveExt for ZipArchive<'_> {
+    fn reader_with_options<'a>(
+        &'a self,
+        cfh: &CentralFileHeader<'_>,
+        password: Option<&[u8]>,
+        options: &DecoderOptions
+    ) -> anyhow::Result<EntryReader<'a>> {
+        let buf = read_data(self, cfh)?;
+
+        let encryption = Encryption::new(
+            cfh.gp_flag,
+            cfh.method,
+            cfh.crc32,
+            cfh.mod_time,
+            cfh.extra_fields()
+        )?;
+        let reader = encryption.decrypt(buf, password)?;
+        let reader = Decoder::with_options(encryption.method(), cfh.gp_flag, Some(cfh.uncomp_size), reader, options)?;
+        // prevent zipbomb
+        let reader = reader.take(cfh.uncomp_size);
+        let reader = if encryption.check_crc() {
+            Crc32Checker::new(reader, cfh.crc32)
+        } else {
+            Crc32Checker::deferred(reader)
+        };
+
+        Ok(reader)
+    }
+
+    fn inflate_into(&self, cfh: &CentralFileHeader<'_>, output: &mut [u8]) -> anyhow::Result<bool> {
+        if cfh.method != compress::DEFLATE || cfh.gp_flag & gp_flag::ENCRYPTED != 0 {
+            return Ok(false);
+        }
+
+        if u64::try_from(output.len()) != Ok(cfh.uncomp_size) {
+            anyhow::bail!("output size does not match uncompressed size: {}", output.len());
+        }
+
+        let buf = read_data(self, cfh)?;
+        inflate(buf, output)?;
+
+        let crc = crc32fast::hash(output);
+        if crc != cfh.crc32 {
+            anyhow::bail!("crc32 check failed. expect: {}, got: {}", cfh.crc32, crc);
+        }
+
+        Ok(true)
+    }
+}
+
+/// Read the compressed data of the entry and check it against the central directory.
+fn read_data<'a>(zip: &'a ZipArchive<'_>, cfh: &CentralFileHeader<'_>) -> anyhow::Result<&'a [u8]> {
+    use anyhow::Context;
+
+    let (lfh, buf) = zip.read(cfh).context("read entry failed")?;
+
+    if let Some(descriptor) = lfh.descriptor.as_ref() {
+        if descriptor.crc32 != cfh.crc32
+            || descriptor.comp_size != cfh.comp_size
+            || descriptor.uncomp_size != cfh.uncomp_size
+        {
+            anyhow::bail!("data descriptor does not match central directory: {:?}", descriptor);
+        }
+    }
+
+    Ok(buf)
+}
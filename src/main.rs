@@ -1,31 +1,63 @@
 mod util;
 
 use std::{ cmp, env, fs };
-use std::io::{ self, Read };
+use std::io::{ self, Read, Write };
 use std::path::{ Path, PathBuf };
+use std::collections::{ HashMap, HashSet };
+use std::ops::{ Deref, Range };
+use std::sync::Mutex;
 use argh::FromArgs;
 use anyhow::Context;
 use bstr::ByteSlice;
 use encoding_rs::Encoding;
 use rayon::prelude::*;
-use memmap2::MmapOptions;
-use flate2::bufread::DeflateDecoder;
-use zip_parser::{ compress, ZipArchive, CentralFileHeader };
+use memmap2::{ Mmap, MmapOptions };
+use zip_parser::{ compress, gp_flag, ZipArchive, CentralFileHeader };
+use zip_parser::stream::ZipStream;
+use zip_parser::scan::LocalEntry;
+use unzrip::{ ZipArchiveExt, Decoder, DecoderOptions, Crc32Checker };
+use unzrip::crypto::{ Encryption, Decryptor, InvalidPassword };
 use util::{
-    Decoder, Crc32Checker, FilenameEncoding,
-    dos2time, path_join, path_open
+    EntryFilter, FilenameEncoding, Overwrite,
+    dos2time, entry_mtime, method_name, path_join, path_open, sanitize_setuid, wildcard_match
 };
 
-#[cfg(feature = "zstd-sys")]
-use zstd::stream::read::Decoder as ZstdDecoder;
+/// Deflate entries up to this size are decompressed at once instead of streaming.
+const INFLATE_AT_ONCE_SIZE: u64 = 1 << 20;
 
 /// unzrip - extract compressed files in a ZIP archive
 #[derive(FromArgs)]
 struct Options {
-    /// path of the ZIP archive(s).
+    /// path of the ZIP archive, `-` to read from stdin,
+    /// followed by the names or wildcards of entries to select.
+    /// wildcards in the archive name select several archives, e.g. '*.zip'.
     #[argh(positional)]
     file: Vec<PathBuf>,
 
+    /// exclude the entries matching the name or wildcard, can be repeated.
+    #[argh(option, short = 'x')]
+    exclude: Vec<String>,
+
+    /// match the names and wildcards case-insensitively.
+    #[argh(switch, short = 'C')]
+    ignore_case: bool,
+
+    /// list the entries of archive instead of extracting them.
+    #[argh(switch, short = 'l')]
+    list: bool,
+
+    /// list the entries verbosely, with method, compressed size and crc.
+    #[argh(switch, short = 'v')]
+    verbose: bool,
+
+    /// test the entries by decompressing them without writing to disk.
+    #[argh(switch, short = 't')]
+    test: bool,
+
+    /// write the selected entries to stdout, nothing else is printed on stdout.
+    #[argh(switch, short = 'p')]
+    pipe: bool,
+
     /// an optional directory to which to extract files.
     #[argh(option, short = 'd')]
     exdir: Option<PathBuf>,
@@ -38,11 +70,47 @@ struct Options {
     /// try to keep the original filename,
     /// which will ignore the charset.
     #[argh(switch)]
-    keep_origin_filename: bool
+    keep_origin_filename: bool,
+
+    /// overwrite existing files.
+    #[argh(switch, short = 'o')]
+    overwrite: bool,
+
+    /// never overwrite existing files, skip them silently.
+    #[argh(switch, short = 'n')]
+    never_overwrite: bool,
+
+    /// replace existing files if the entry is newer, and extract missing files.
+    #[argh(switch, short = 'u')]
+    update: bool,
+
+    /// replace existing files if the entry is newer, and do not extract other files.
+    #[argh(switch, short = 'f')]
+    freshen: bool,
+
+    /// rebuild the entry list by scanning local file headers,
+    /// if the central directory is truncated or corrupted.
+    #[argh(switch)]
+    recover: bool,
+
+    /// password to decrypt encrypted entries,
+    /// also read from `UNZRIP_PASSWORD` environment variable,
+    /// or asked interactively if not given.
+    #[argh(option, short = 'P')]
+    password: Option<String>,
+
+    /// read the password from the first line of file.
+    #[argh(option)]
+    password_file: Option<PathBuf>,
+
+    /// the maximum window size of zstd frames as a power of 2,
+    /// default is 27 (128MiB).
+    #[argh(option)]
+    zstd_window_log_max: Option<u32>
 }
 
 fn main() -> anyhow::Result<()> {
-    let options: Options = argh::from_env();
+    let options = options_from_env();
 
     let target_dir = if let Some(exdir) = options.exdir {
         exdir
@@ -57,16 +125,218 @@ fn main() -> anyhow::Result<()> {
     } else {
         FilenameEncoding::Auto
     };
+    let password = match (options.password, options.password_file) {
+        (Some(password), None) => Some(password.into_bytes()),
+        (None, Some(path)) => Some(read_password_file(&path)?),
+        (None, None) => env::var("UNZRIP_PASSWORD").ok().map(String::into_bytes),
+        (Some(_), Some(_)) => anyhow::bail!("--password and --password-file cannot be used together")
+    };
+    let overwrite = match (options.overwrite, options.never_overwrite, options.update, options.freshen) {
+        (false, false, false, false) => Overwrite::Error,
+        (true, false, false, false) => Overwrite::Always,
+        (false, true, false, false) => Overwrite::Never,
+        (false, false, true, false) => Overwrite::Update,
+        (false, false, false, true) => Overwrite::Freshen,
+        _ => anyhow::bail!("-o, -n, -u and -f cannot be used together")
+    };
+    let decoder_options = match options.zstd_window_log_max {
+        Some(log) => DecoderOptions::default().zstd_window_log_max(log)?,
+        None => DecoderOptions::default()
+    };
 
-    for file in options.file.iter() {
-        unzip(encoding, &target_dir, file)
-            .with_context(|| file.display().to_string())?;
+    let (archive, patterns) = options.file.split_first().context("missing archive")?;
+    let archives = expand_archive(archive)?;
+    let filter = EntryFilter::new(
+        patterns.iter().map(|pattern| pattern.to_string_lossy().into_owned()).collect(),
+        options.exclude,
+        options.ignore_case
+    );
+
+    for file in archives.iter() {
+        let password = Password::new(file, password.clone());
+
+        let result = if options.pipe {
+            pipe(encoding, file, &filter, &password, &decoder_options)
+        } else if options.list || options.verbose {
+            list(encoding, file, &filter, options.verbose)
+        } else if options.test {
+            test(encoding, file, &filter, &password, &decoder_options)
+        } else if file.as_os_str() == "-" {
+            unzip_stream(encoding, &target_dir, overwrite, &filter, &decoder_options, io::stdin().lock())
+        } else if options.recover {
+            unzip_recover(encoding, &target_dir, overwrite, file, &filter, &password, &decoder_options)
+        } else {
+            unzip(encoding, &target_dir, overwrite, file, &filter, &password, &decoder_options)
+        };
+
+        // the reader of stdout has gone, such as `unzrip -l x.zip | head`
+        if result.as_ref().is_err_and(is_broken_pipe) {
+            return Ok(());
+        }
+        result.with_context(|| file.display().to_string())?;
+    }
+
+    if let Some(pattern) = filter.unmatched() {
+        anyhow::bail!("filename not matched: {}", pattern);
     }
 
     Ok(())
 }
 
-fn unzip(encoding: FilenameEncoding, target_dir: &Path, path: &Path) -> anyhow::Result<()> {
+/// Expand the wildcards in the file name of archive like Info-ZIP unzip,
+/// the path is taken literally if it exists or has no wildcard.
+fn expand_archive(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let pattern = match path.file_name() {
+        Some(name) if path.as_os_str() != "-" && !path.exists() => name.to_string_lossy(),
+        _ => return Ok(vec![path.to_owned()])
+    };
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![path.to_owned()]);
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
+    let mut archives = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| dir.display().to_string())? {
+        let entry = entry?;
+        if wildcard_match(&pattern, &entry.file_name().to_string_lossy())
+            && entry.file_type()?.is_file()
+        {
+            archives.push(dir.join(entry.file_name()));
+        }
+    }
+
+    if archives.is_empty() {
+        anyhow::bail!("archive not matched: {}", path.display());
+    }
+    archives.sort();
+
+    Ok(archives)
+}
+
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|err| err.downcast_ref::<io::Error>())
+        .any(|err| err.kind() == io::ErrorKind::BrokenPipe)
+}
+
+/// Like `argh::from_env`, but also accept `-` as positional argument.
+fn options_from_env() -> Options {
+    // argh takes `-` as an option, so replace it until parsed, keeping the order of positional arguments
+    const STDIN: &str = "\0-";
+
+    let mut args: Vec<String> = env::args().collect();
+
+    args.iter_mut()
+        .skip(1)
+        .take_while(|arg| *arg != "--")
+        .filter(|arg| *arg == "-")
+        .for_each(|arg| *arg = STDIN.into());
+
+    let cmd = args.first()
+        .and_then(|arg| Path::new(arg).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or("unzrip");
+    let args: Vec<&str> = args.iter().skip(1).map(String::as_str).collect();
+
+    let mut options = Options::from_args(&[cmd], &args).unwrap_or_else(|early_exit| {
+        std::process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
+            },
+            Err(()) => {
+                eprintln!("{}\nRun {} --help for more information.", early_exit.output, cmd);
+                1
+            }
+        })
+    });
+
+    options.file.iter_mut()
+        .filter(|file| file.as_os_str() == STDIN)
+        .for_each(|file| *file = "-".into());
+
+    options
+}
+
+fn read_password_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let buf = fs::read(path).with_context(|| path.display().to_string())?;
+    let line = buf.lines().next().unwrap_or_default();
+    Ok(line.to_vec())
+}
+
+/// The password of an archive.
+///
+/// If no password is given, it is asked when an encrypted entry is met,
+/// and asked again if it is wrong. The accepted one is used for the rest of the archive.
+struct Password {
+    archive: String,
+    current: Mutex<Option<Vec<u8>>>,
+    interactive: bool
+}
+
+impl Password {
+    fn new(archive: &Path, password: Option<Vec<u8>>) -> Password {
+        use std::io::IsTerminal;
+
+        Password {
+            archive: archive.display().to_string(),
+            interactive: password.is_none() && io::stdin().is_terminal(),
+            current: Mutex::new(password)
+        }
+    }
+
+    fn get(&self) -> Option<Vec<u8>> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Call `f` with the password, ask for another one if it is wrong.
+    fn try_with<T>(&self, name: &[u8], f: impl Fn(Option<&[u8]>) -> anyhow::Result<T>)
+        -> anyhow::Result<T>
+    {
+        let mut tried = self.get();
+
+        loop {
+            let err = match f(tried.as_deref()) {
+                Err(err) if self.interactive && err.is::<InvalidPassword>() => err,
+                result => return result
+            };
+
+            let mut current = self.current.lock().unwrap();
+
+            // unless another entry has changed it, ask for the password
+            if *current == tried {
+                let prompt = if tried.is_none() {
+                    format!("[{}] {} password: ", self.archive, name.as_bstr())
+                } else {
+                    "password incorrect--reenter: ".into()
+                };
+                let password = rpassword::prompt_password(prompt)?;
+
+                // give up this entry
+                if password.is_empty() {
+                    return Err(err);
+                }
+
+                *current = Some(password.into_bytes());
+            }
+
+            tried = current.clone();
+        }
+    }
+}
+
+fn unzip(
+    encoding: FilenameEncoding,
+    target_dir: &Path,
+    overwrite: Overwrite,
+    path: &Path,
+    filter: &EntryFilter,
+    password: &Password,
+    decoder_options: &DecoderOptions
+) -> anyhow::Result<()> {
     println!("Archive: {}", path.display());
 
     let fd = fs::File::open(path)?;
@@ -75,6 +345,8 @@ fn unzip(encoding: FilenameEncoding, target_dir: &Path, path: &Path) -> anyhow::
     };
 
     let zip = ZipArchive::parse(&buf)?;
+    zip.check_overlap()?;
+
     let len: usize = zip.eocdr().cd_entries.try_into()?;
     let len = cmp::min(len, 128);
 
@@ -84,44 +356,564 @@ fn unzip(encoding: FilenameEncoding, target_dir: &Path, path: &Path) -> anyhow::
             acc
         }))?
         .par_iter()
-        .try_for_each(|cfh| do_entry(encoding, &zip, &cfh, target_dir))?;
+        .try_for_each(|cfh| do_entry(encoding, &zip, cfh, target_dir, overwrite, filter, password, decoder_options))?;
 
     Ok(())
 }
 
+/// The whole archive, mapped from file or read from stdin.
+enum ArchiveBuf {
+    Mmap(Mmap),
+    Stdin(Vec<u8>)
+}
+
+impl ArchiveBuf {
+    fn open(path: &Path) -> anyhow::Result<ArchiveBuf> {
+        if path.as_os_str() == "-" {
+            let mut buf = Vec::new();
+            io::stdin().lock().read_to_end(&mut buf)?;
+            Ok(ArchiveBuf::Stdin(buf))
+        } else {
+            let fd = fs::File::open(path)?;
+            let buf = unsafe {
+                MmapOptions::new().map_copy_read_only(&fd)?
+            };
+            Ok(ArchiveBuf::Mmap(buf))
+        }
+    }
+}
+
+impl Deref for ArchiveBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ArchiveBuf::Mmap(buf) => buf,
+            ArchiveBuf::Stdin(buf) => buf
+        }
+    }
+}
+
+fn list(encoding: FilenameEncoding, path: &Path, filter: &EntryFilter, verbose: bool) -> anyhow::Result<()> {
+    fn ratio(size: u64, comp_size: u64) -> i64 {
+        if size == 0 {
+            0
+        } else {
+            100 - (comp_size as f64 * 100.0 / size as f64).round() as i64
+        }
+    }
+
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+
+    writeln!(stdout, "Archive: {}", path.display())?;
+
+    let buf = ArchiveBuf::open(path)?;
+    let zip = ZipArchive::parse(&buf)?;
+
+    if verbose {
+        writeln!(stdout, " Length   Method    Size  Cmpr    Date    Time   CRC-32   Name")?;
+        writeln!(stdout, "--------  ------  ------- ---- ---------- ----- --------  ----")?;
+    } else {
+        writeln!(stdout, "  Length      Date    Time    Name")?;
+        writeln!(stdout, "---------  ---------- -----   ----")?;
+    }
+
+    let (mut count, mut total_size, mut total_comp_size) = (0u64, 0u64, 0u64);
+
+    for cfh in zip.entries()? {
+        let cfh = cfh?;
+        let path = encoding.decode(cfh.name)?;
+        if !filter.is_match(&path) {
+            continue;
+        }
+
+        let time = match dos2time(cfh.mod_date, cfh.mod_time) {
+            Ok(time) => format!("{} {:02}:{:02}", time.date(), time.hour(), time.minute()),
+            Err(_) => "0000-00-00 00:00".into()
+        };
+
+        if verbose {
+            writeln!(stdout, "{:>8}  {:<6} {:>8} {:>3}% {} {:08x}  {}",
+                cfh.uncomp_size,
+                method_name(cfh.method, cfh.gp_flag),
+                cfh.comp_size,
+                ratio(cfh.uncomp_size, cfh.comp_size),
+                time,
+                cfh.crc32,
+                path.display()
+            )?;
+        } else {
+            writeln!(stdout, "{:>9}  {}   {}", cfh.uncomp_size, time, path.display())?;
+        }
+
+        count += 1;
+        total_size += cfh.uncomp_size;
+        total_comp_size += cfh.comp_size;
+    }
+
+    let files = if count == 1 { "file" } else { "files" };
+
+    if verbose {
+        writeln!(stdout, "--------          -------  ---                            -------")?;
+        writeln!(stdout, "{:>8}         {:>8} {:>3}%                            {} {}",
+            total_size,
+            total_comp_size,
+            ratio(total_size, total_comp_size),
+            count,
+            files
+        )?;
+    } else {
+        writeln!(stdout, "---------                     -------")?;
+        writeln!(stdout, "{:>9}                     {} {}", total_size, count, files)?;
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn test(
+    encoding: FilenameEncoding,
+    path: &Path,
+    filter: &EntryFilter,
+    password: &Password,
+    decoder_options: &DecoderOptions
+) -> anyhow::Result<()> {
+    fn test_entry(
+        zip: &ZipArchive<'_>,
+        cfh: &CentralFileHeader<'_>,
+        password: &Password,
+        decoder_options: &DecoderOptions
+    ) -> anyhow::Result<()> {
+        let mut reader = password.try_with(cfh.name, |password| zip.reader_with_options(cfh, password, decoder_options))?;
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(())
+    }
+
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    writeln!(stdout, "Archive: {}", path.display())?;
+
+    let buf = ArchiveBuf::open(path)?;
+    let zip = ZipArchive::parse(&buf)?;
+    zip.check_overlap()?;
+
+    let entries = zip.entries()?.collect::<Result<Vec<_>, _>>()?;
+
+    // test in parallel, but print on this thread so that a closed stdout is an error, not a panic
+    let results = entries.par_iter()
+        .filter_map(|cfh| {
+            let name = match encoding.decode(cfh.name) {
+                Ok(path) if !filter.is_match(&path) => return None,
+                Ok(path) => path.display().to_string(),
+                Err(_) => cfh.name.as_bstr().to_string()
+            };
+
+            Some((name, test_entry(&zip, cfh, password, decoder_options)))
+        })
+        .collect::<Vec<_>>();
+
+    let mut failed = 0;
+    for (name, result) in results {
+        match result {
+            Ok(()) => writeln!(stdout, "    testing: {}   OK", name)?,
+            Err(err) => {
+                writeln!(stdout, "    testing: {}   FAIL", name)?;
+                eprintln!("{}: {:?}", name, err);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed != 0 {
+        stdout.flush()?;
+        anyhow::bail!("{} entries failed the test", failed);
+    }
+
+    writeln!(stdout, "No errors detected in compressed data of {}.", path.display())?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn pipe(
+    encoding: FilenameEncoding,
+    path: &Path,
+    filter: &EntryFilter,
+    password: &Password,
+    decoder_options: &DecoderOptions
+) -> anyhow::Result<()> {
+    let buf = ArchiveBuf::open(path)?;
+    let zip = ZipArchive::parse(&buf)?;
+    zip.check_overlap()?;
+
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+
+    for cfh in zip.entries()? {
+        let cfh = cfh?;
+        let name = encoding.decode(cfh.name)?;
+
+        if filter.is_match(&name) {
+            let mut reader = password.try_with(cfh.name, |password| zip.reader_with_options(&cfh, password, decoder_options))
+                .with_context(|| name.display().to_string())?;
+            io::copy(&mut reader, &mut stdout).with_context(|| name.display().to_string())?;
+        }
+    }
+
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn unzip_recover(
+    encoding: FilenameEncoding,
+    target_dir: &Path,
+    overwrite: Overwrite,
+    path: &Path,
+    filter: &EntryFilter,
+    password: &Password,
+    decoder_options: &DecoderOptions
+) -> anyhow::Result<()> {
+    fn report<T>(result: &anyhow::Result<T>, name: &[u8]) -> bool {
+        if let Err(err) = result {
+            eprintln!("{}: {:?}", name.as_bstr(), err);
+        }
+
+        result.is_err()
+    }
+
+    println!("Archive: {}", path.display());
+
+    let fd = fs::File::open(path)?;
+    let buf = unsafe {
+        MmapOptions::new().map_copy_read_only(&fd)?
+    };
+    let buf = &buf[..];
+
+    let mut done = HashSet::new();
+    let mut covered: Vec<Range<u64>> = Vec::new();
+    let mut failed = 0;
+
+    // extract what is left of the central directory
+    if let Ok(zip) = ZipArchive::parse(buf) {
+        let entries = zip.entries()
+            .into_iter()
+            .flatten()
+            .map_while(Result::ok)
+            .collect::<Vec<_>>();
+
+        failed += entries.par_iter()
+            .filter(|cfh| {
+                let result = do_entry(encoding, &zip, cfh, target_dir, overwrite, filter, password, decoder_options);
+                report(&result, cfh.name)
+            })
+            .count();
+        done.extend(entries.iter().map(|cfh| cfh.lfh_offset + zip.prefix_len()));
+
+        // the headers inside these entries belong to nested archives
+        covered.extend(entries.iter().filter_map(|cfh| {
+            let (_, data) = zip.read(cfh).ok()?;
+            let start = cfh.lfh_offset + zip.prefix_len();
+            let end = data.as_ptr_range().end as usize - buf.as_ptr() as usize;
+            Some(start..end as u64)
+        }));
+    }
+
+    // an entry with corrupted size hides the entries after it,
+    // so rescan from each failed or overrun entry until nothing new is found
+    let mut starts = vec![0];
+
+    while let Some(start) = starts.pop() {
+        let entries = ZipArchive::scan_local_headers_from(buf, start)
+            .filter(|entry| !covered.iter().any(|range| range.contains(&entry.offset)))
+            .filter(|entry| done.insert(entry.offset))
+            .collect::<Vec<_>>();
+
+        let rescan = entries.par_iter()
+            .filter_map(|entry| {
+                let result = do_local_entry(encoding, entry, target_dir, overwrite, filter, password, decoder_options);
+                let failed = report(&result, entry.header.name);
+                (failed || matches!(result, Ok(false))).then_some((entry.offset + 4, failed))
+            })
+            .collect::<Vec<_>>();
+
+        failed += rescan.iter().filter(|(_, failed)| *failed).count();
+        starts.extend(rescan.into_iter().map(|(start, _)| start));
+    }
+
+    if failed != 0 {
+        anyhow::bail!("{} entries could not be recovered", failed);
+    }
+
+    Ok(())
+}
+
+fn do_local_entry(
+    encoding: FilenameEncoding,
+    entry: &LocalEntry<'_>,
+    target_dir: &Path,
+    overwrite: Overwrite,
+    filter: &EntryFilter,
+    password: &Password,
+    decoder_options: &DecoderOptions
+) -> anyhow::Result<bool> {
+    let lfh = &entry.header;
+    let name = lfh.name;
+
+    if !filter.is_match(&encoding.decode(name)?) {
+        return Ok(true);
+    }
+
+    if is_dir(name, lfh.method, entry.data.is_empty()) {
+        #[cfg(unix)]
+        let name = name.trim_end_with(|c| c == '\\');
+        let path = encoding.decode(name)?;
+        do_dir(target_dir, &path, overwrite)?
+    } else {
+        let path = encoding.decode(name)?;
+        let mtime = entry_mtime(lfh.mod_date, lfh.mod_time, lfh.extra_fields())?;
+
+        let encryption = Encryption::new(
+            lfh.gp_flag,
+            lfh.method,
+            entry.crc32(),
+            lfh.mod_time,
+            lfh.extra_fields()
+        )?;
+        let reader = password.try_with(name, |password| encryption.decrypt(entry.data, password))?;
+        let reader = Decoder::with_options(
+            encryption.method(),
+            lfh.gp_flag,
+            Some(entry.uncomp_size()),
+            reader,
+            decoder_options
+        )?;
+        // prevent zipbomb
+        let reader = reader.take(entry.uncomp_size());
+        let mut reader = if encryption.check_crc() {
+            Crc32Checker::new(reader, entry.crc32())
+        } else {
+            Crc32Checker::deferred(reader)
+        };
+
+        if write_file(target_dir, &path, &mut reader, mtime, overwrite)?.is_none() {
+            return Ok(true);
+        }
+        println!("  recovered: {}", path.display());
+
+        // data left after the end of stream means the compressed size may be corrupted
+        let decoder = reader.get_mut().get_mut();
+        let overrun = io::copy(decoder, &mut io::sink())?;
+        if overrun != 0 || matches!(decoder.get_ref(), Some(Decryptor::None(rest)) if !rest.is_empty()) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn do_entry(
     encoding: FilenameEncoding,
     zip: &ZipArchive<'_>,
     cfh: &CentralFileHeader<'_>,
-    target_dir: &Path
+    target_dir: &Path,
+    overwrite: Overwrite,
+    filter: &EntryFilter,
+    password: &Password,
+    decoder_options: &DecoderOptions
 ) -> anyhow::Result<()> {
-    let (_lfh, buf) = zip.read(cfh).context("read entry failed")?;
+    let name = cfh.name;
 
-    if cfh.gp_flag & 1 != 0 {
-        anyhow::bail!("encrypt is not supported");
+    if !filter.is_match(&encoding.decode(name)?) {
+        return Ok(());
     }
 
-    let name = cfh.name;
-
-    if (name.ends_with_str("/") || name.ends_with_str("\\"))
-        && cfh.method == compress::STORE
-        && buf.is_empty()
+    // small deflate entry is decompressed at once
+    if cfh.method == compress::DEFLATE
+        && cfh.uncomp_size <= INFLATE_AT_ONCE_SIZE
+        && !is_dir(name, cfh.method, cfh.comp_size == 0)
     {
+        let mut buf = vec![0; cfh.uncomp_size as usize];
+
+        if zip.inflate_into(cfh, &mut buf).with_context(|| name.as_bstr().to_string())? {
+            let path = encoding.decode(name)?;
+            return do_file(cfh, target_dir, &path, &mut buf.as_slice(), overwrite);
+        }
+    }
+
+    let mut reader = password.try_with(name, |password| zip.reader_with_options(cfh, password, decoder_options))
+        .with_context(|| name.as_bstr().to_string())?;
+
+    if is_dir(name, cfh.method, cfh.comp_size == 0) {
         #[cfg(unix)]
         let name = name.trim_end_with(|c| c == '\\');
         let path = encoding.decode(name)?;
-        do_dir(target_dir, &path)?
+        do_dir(target_dir, &path, overwrite)?
     } else {
-        let path = encoding.decode(&name)?;
-        do_file(cfh, target_dir, &path, buf)?;
+        let path = encoding.decode(name)?;
+        do_file(cfh, target_dir, &path, &mut reader, overwrite)?;
     }
 
     Ok(())
 }
 
-fn do_dir(target_dir: &Path, path: &Path) -> anyhow::Result<()> {
+fn unzip_stream(
+    encoding: FilenameEncoding,
+    target_dir: &Path,
+    overwrite: Overwrite,
+    filter: &EntryFilter,
+    decoder_options: &DecoderOptions,
+    reader: impl io::BufRead
+) -> anyhow::Result<()> {
+    /// What is known of an extracted entry, to reconcile with the central directory.
+    struct Extracted {
+        crc32: u32,
+        comp_size: u64,
+        uncomp_size: u64,
+        path: Option<PathBuf>
+    }
+
+    println!("Archive: -");
+
+    let mut stream = ZipStream::new(reader);
+    let mut extracted = HashMap::new();
+    let mut skipped = HashSet::new();
+
+    while let Some(mut entry) = stream.next_entry()? {
+        let lfh = &entry.header;
+        let name = lfh.name;
+        let key = name.to_vec();
+
+        if !filter.is_match(&encoding.decode(name)?) {
+            // the end of unsized data is only known by decompressing it
+            if entry.is_unsized() {
+                let (method, gp_flag) = (lfh.method, lfh.gp_flag);
+                let mut reader = Decoder::with_options(method, gp_flag, None, &mut entry, decoder_options)?.single_frame();
+                io::copy(&mut reader, &mut io::sink())?;
+            }
+
+            entry.finish()?;
+            skipped.insert(key);
+            continue;
+        }
+
+        if lfh.gp_flag & gp_flag::ENCRYPTED != 0 {
+            anyhow::bail!("encrypted entry is not supported in stream: {}", name.as_bstr());
+        }
+
+        if is_dir(name, lfh.method, lfh.comp_size == 0 && !entry.is_unsized()) {
+            #[cfg(unix)]
+            let name = name.trim_end_with(|c| c == '\\');
+            let path = encoding.decode(name)?;
+            do_dir(target_dir, &path, overwrite)?;
+
+            entry.finish()?;
+            extracted.insert(key, Extracted { crc32: 0, comp_size: 0, uncomp_size: 0, path: None });
+        } else {
+            let path = encoding.decode(name)?;
+            let deferred = lfh.gp_flag & gp_flag::DATA_DESCRIPTOR != 0;
+            let (crc32, comp_size, uncomp_size) = (lfh.crc32, lfh.comp_size, lfh.uncomp_size);
+            let mtime = entry_mtime(lfh.mod_date, lfh.mod_time, lfh.extra_fields())?;
+
+            // the data of skipped file is still read to the end
+            let size = (!deferred).then_some(uncomp_size);
+            let reader = Decoder::with_options(lfh.method, lfh.gp_flag, size, &mut entry, decoder_options)?.single_frame();
+            let (crc, len, written) = if deferred {
+                let mut reader = Crc32Checker::deferred(reader);
+                let written = write_file(target_dir, &path, &mut reader, mtime, overwrite)?.is_some();
+                io::copy(&mut reader, &mut io::sink())?;
+                (reader.crc32(), reader.amount(), written)
+            } else {
+                // prevent zipbomb
+                let reader = reader.take(uncomp_size);
+                let mut reader = Crc32Checker::new(reader, crc32);
+                let written = write_file(target_dir, &path, &mut reader, mtime, overwrite)?.is_some();
+                io::copy(&mut reader, &mut io::sink())?;
+                (reader.crc32(), reader.amount(), written)
+            };
+
+            // the compressed size of unsized entry is only known after decoding
+            let comp_len = if entry.is_unsized() {
+                entry.read_len()
+            } else {
+                comp_size
+            };
+
+            let (comp_size, uncomp_size) = match entry.finish()? {
+                Some(descriptor) => {
+                    if descriptor.crc32 != crc {
+                        anyhow::bail!("crc32 check failed. expect: {}, got: {}", descriptor.crc32, crc);
+                    }
+
+                    if descriptor.comp_size != comp_len || descriptor.uncomp_size != len {
+                        anyhow::bail!("data descriptor does not match data: {:?}", descriptor);
+                    }
+
+                    (descriptor.comp_size, descriptor.uncomp_size)
+                },
+                None => (comp_size, uncomp_size)
+            };
+
+            if len != uncomp_size {
+                anyhow::bail!("uncompressed size does not match. expect: {}, got: {}", uncomp_size, len);
+            }
+
+            let path = if written {
+                println!("  inflating: {}", path.display());
+                Some(path_join(target_dir, &path)?)
+            } else {
+                None
+            };
+
+            extracted.insert(key, Extracted { crc32: crc, comp_size, uncomp_size, path });
+        }
+    }
+
+    // reconcile with the central directory
+    let cd = stream.into_central_directory()?;
+
+    for cfh in cd.entries()? {
+        let cfh = cfh?;
+
+        if skipped.remove(cfh.name) {
+            continue;
+        }
+
+        match extracted.remove(cfh.name) {
+            Some(entry) if entry.crc32 != cfh.crc32 =>
+                anyhow::bail!("crc32 does not match central directory: {}", cfh.name.as_bstr()),
+            Some(entry) if entry.comp_size != cfh.comp_size || entry.uncomp_size != cfh.uncomp_size =>
+                anyhow::bail!("sizes do not match central directory: {}", cfh.name.as_bstr()),
+            Some(entry) => {
+                if let (Some(path), Some(perm)) = (entry.path, unix_permissions(&cfh)) {
+                    fs::set_permissions(path, perm)?;
+                }
+            },
+            None => anyhow::bail!("missing local file header: {}", cfh.name.as_bstr())
+        }
+    }
+
+    if let Some(name) = extracted.keys().next() {
+        anyhow::bail!("not in central directory: {}", name.as_bstr());
+    }
+
+    Ok(())
+}
+
+fn is_dir(name: &[u8], method: u16, empty: bool) -> bool {
+    (name.ends_with_str("/") || name.ends_with_str("\\"))
+        && method == compress::STORE
+        && empty
+}
+
+fn do_dir(target_dir: &Path, path: &Path, overwrite: Overwrite) -> anyhow::Result<()> {
     let target = path_join(target_dir, path)?;
 
+    // only existing files are freshened
+    if overwrite == Overwrite::Freshen && !target.is_dir() {
+        return Ok(());
+    }
+
     fs::create_dir_all(target)
         .or_else(|err| if err.kind() == io::ErrorKind::AlreadyExists {
             Ok(())
@@ -139,43 +931,60 @@ fn do_file(
     cfh: &CentralFileHeader,
     target_dir: &Path,
     path: &Path,
-    buf: &[u8]
+    reader: &mut impl Read,
+    overwrite: Overwrite
 ) -> anyhow::Result<()> {
+    let mtime = entry_mtime(cfh.mod_date, cfh.mod_time, cfh.extra_fields())?;
+    let fd = match write_file(target_dir, path, reader, mtime, overwrite)? {
+        Some(fd) => fd,
+        None => return Ok(())
+    };
+
+    if let Some(perm) = unix_permissions(cfh) {
+        fd.set_permissions(perm)?;
+    }
+
+    println!("  inflating: {}", path.display());
+
+    Ok(())
+}
+
+fn write_file(
+    target_dir: &Path,
+    path: &Path,
+    reader: &mut impl Read,
+    mtime: filetime::FileTime,
+    overwrite: Overwrite
+) -> anyhow::Result<Option<fs::File>> {
     let target = path_join(target_dir, path)?;
 
-    let reader = match cfh.method {
-        compress::STORE => Decoder::None(buf),
-        compress::DEFLATE => Decoder::Deflate(DeflateDecoder::new(buf)),
-        #[cfg(feature = "zstd-sys")]
-        compress::ZSTD => Decoder::Zstd(ZstdDecoder::with_buffer(buf)?),
-        _ => anyhow::bail!("compress method is not supported: {}", cfh.method)
-    };
-    // prevent zipbomb
-    let reader = reader.take(cfh.uncomp_size.into());
-    let mut reader = Crc32Checker::new(reader, cfh.crc32);
-
-    let mtime = {
-        let time = dos2time(cfh.mod_date, cfh.mod_time)?.assume_utc();
-        let unix_timestamp = time.unix_timestamp();
-        let nanos = time.nanosecond();
-        filetime::FileTime::from_unix_time(unix_timestamp, nanos)
-    };
+    // the file is skipped
+    if !overwrite.prepare(&target, mtime).with_context(|| path.display().to_string())? {
+        return Ok(None);
+    }
 
     let mut fd = path_open(&target).with_context(|| path.display().to_string())?;
 
-    io::copy(&mut reader, &mut fd)?;
+    io::copy(reader, &mut fd)?;
 
     filetime::set_file_handle_times(&fd, None, Some(mtime))?;
 
-    #[cfg(unix)]
-    if cfh.ext_attrs != 0 && cfh.made_by_ver >> 8 == zip_parser::system::UNIX {
-        use std::os::unix::fs::PermissionsExt;
+    Ok(Some(fd))
+}
+
+#[cfg(unix)]
+fn unix_permissions(cfh: &CentralFileHeader) -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
 
+    if cfh.ext_attrs != 0 && cfh.made_by_ver >> 8 == zip_parser::system::UNIX {
         let perm = fs::Permissions::from_mode(cfh.ext_attrs >> 16);
-        fd.set_permissions(util::sanitize_setuid(perm))?;
+        Some(sanitize_setuid(perm))
+    } else {
+        None
     }
+}
 
-    println!("  inflating: {}", path.display());
-
-    Ok(())
+#[cfg(not(unix))]
+fn unix_permissions(_cfh: &CentralFileHeader) -> Option<fs::Permissions> {
+    None
 }
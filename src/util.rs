@@ -1,68 +1,14 @@
 use std::{ io, fs };
 use std::path::{ Path, PathBuf, Component };
 use std::borrow::Cow;
+use std::sync::atomic::{ AtomicBool, Ordering };
 use anyhow::Context;
 use bstr::ByteSlice;
 use encoding_rs::Encoding;
-use flate2::bufread::DeflateDecoder;
+use filetime::FileTime;
+use zip_parser::compress;
+use zip_parser::extra::{ self, ExtraFields, ExtendedTimestamp };
 
-#[cfg(feature = "zstd-sys")]
-use zstd::stream::read::Decoder as ZstdDecoder;
-
-
-pub enum Decoder<R: io::BufRead> {
-    None(R),
-    Deflate(DeflateDecoder<R>),
-    #[cfg(feature = "zstd-sys")]
-    Zstd(ZstdDecoder<'static, R>)
-}
-
-impl<R: io::BufRead> io::Read for Decoder<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self {
-            Decoder::None(reader) => io::Read::read(reader, buf),
-            Decoder::Deflate(reader) => io::Read::read(reader, buf),
-            #[cfg(feature = "zstd-sys")]
-            Decoder::Zstd(reader) => io::Read::read(reader, buf)
-        }
-    }
-}
-
-pub struct Crc32Checker<R> {
-    reader: R,
-    expect: u32,
-    hasher: crc32fast::Hasher,
-}
-
-impl<R> Crc32Checker<R> {
-    pub fn new(reader: R, expect: u32) -> Crc32Checker<R> {
-        Crc32Checker {
-            reader, expect,
-            hasher: crc32fast::Hasher::new()
-        }
-    }
-}
-
-impl<R: io::Read> io::Read for Crc32Checker<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let n = io::Read::read(&mut self.reader, buf)?;
-
-        if n == 0 {
-            let crc = self.hasher.clone().finalize();
-            if crc != self.expect {
-                let msg = format!("crc32 check failed. expect: {}, got: {}",
-                    self.expect,
-                    crc
-                );
-                return Err(io::Error::new(io::ErrorKind::InvalidData, msg))
-            }
-        } else {
-            self.hasher.update(&buf[..n]);
-        }
-
-        Ok(n)
-    }
-}
 
 #[derive(Clone, Copy)]
 pub enum FilenameEncoding {
@@ -103,6 +49,33 @@ impl FilenameEncoding {
     }
 }
 
+/// The short name of compress method, as shown by `unzip -v`.
+pub fn method_name(method: u16, gp_flag: u16) -> Cow<'static, str> {
+    Cow::Borrowed(match method {
+        compress::STORE => "Stored",
+        compress::SHRINK => "Shrunk",
+        compress::REDUCE1 => "Reduce1",
+        compress::REDUCE2 => "Reduce2",
+        compress::REDUCE3 => "Reduce3",
+        compress::REDUCE4 => "Reduce4",
+        compress::IMPLODE => "Implode",
+        // the level of compression
+        compress::DEFLATE => match (gp_flag >> 1) & 0x3 {
+            0 => "Defl:N",
+            1 => "Defl:X",
+            2 => "Defl:F",
+            _ => "Defl:S"
+        },
+        compress::DEFLATE64 => "Def64",
+        compress::BZIP2 => "BZip2",
+        compress::LZMA => "LZMA",
+        compress::ZSTD => "Zstd",
+        compress::XZ => "XZ",
+        unzrip::crypto::AES_METHOD => "AES",
+        _ => return Cow::Owned(format!("Unk:{:03}", method))
+    })
+}
+
 pub fn dos2time(dos_date: u16, dos_time: u16)
     -> anyhow::Result<time::PrimitiveDateTime>
 {
@@ -123,7 +96,7 @@ pub fn dos2time(dos_date: u16, dos_time: u16)
         sec.try_into().context("sec cast")?
     )?;
     let date = time::Date::from_calendar_date(
-        year.try_into().context("year cast")?,
+        year.into(),
         mon,
         day.try_into().context("day cast")?
     )?;
@@ -131,6 +104,68 @@ pub fn dos2time(dos_date: u16, dos_time: u16)
     Ok(date.with_time(time))
 }
 
+/// The modification time of entry,
+/// from the extended timestamp if any, otherwise the dos date time.
+pub fn entry_mtime(mod_date: u16, mod_time: u16, mut extra_fields: ExtraFields<'_>)
+    -> anyhow::Result<FileTime>
+{
+    let mtime = extra_fields
+        .find_map(|field| match field {
+            Ok((extra::id::EXTENDED_TIMESTAMP, data)) => ExtendedTimestamp::parse(data).ok(),
+            _ => None
+        })
+        .and_then(|timestamp| timestamp.mtime);
+
+    if let Some(mtime) = mtime {
+        return Ok(FileTime::from_unix_time(mtime.into(), 0));
+    }
+
+    let time = dos2time(mod_date, mod_time)?.assume_utc();
+    Ok(FileTime::from_unix_time(time.unix_timestamp(), time.nanosecond()))
+}
+
+/// What to do if the file to extract already exists.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// fail on the existing file
+    Error,
+    /// always replace the existing file
+    Always,
+    /// never replace the existing file
+    Never,
+    /// replace the existing file if it is older, and extract missing files
+    Update,
+    /// only replace the existing file if it is older
+    Freshen
+}
+
+impl Overwrite {
+    /// Whether to extract the entry modified at `mtime` to `path`,
+    /// the existing file is removed if it is to be replaced.
+    pub fn prepare(self, path: &Path, mtime: FileTime) -> io::Result<bool> {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(self != Overwrite::Freshen),
+            Err(err) => return Err(err)
+        };
+
+        let replace = match self {
+            // let it fail when creating
+            Overwrite::Error => return Ok(true),
+            Overwrite::Always => true,
+            Overwrite::Never => false,
+            Overwrite::Update | Overwrite::Freshen =>
+                FileTime::from_last_modification_time(&metadata) < mtime
+        };
+
+        if replace {
+            fs::remove_file(path)?;
+        }
+
+        Ok(replace)
+    }
+}
+
 pub fn path_join(base: &Path, path: &Path) -> anyhow::Result<PathBuf> {
     // check path
     path.components()
@@ -178,6 +213,137 @@ pub fn path_open(path: &Path) -> io::Result<fs::File> {
     }
 }
 
+/// Select entries by names or wildcards, like Info-ZIP unzip.
+pub struct EntryFilter {
+    include: Vec<(String, AtomicBool)>,
+    exclude: Vec<String>,
+    ignore_case: bool
+}
+
+impl EntryFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>, ignore_case: bool) -> EntryFilter {
+        let fold = |pattern: String| if ignore_case {
+            pattern.to_lowercase()
+        } else {
+            pattern
+        };
+
+        EntryFilter {
+            include: include.into_iter()
+                .map(|pattern| (fold(pattern), AtomicBool::new(false)))
+                .collect(),
+            exclude: exclude.into_iter().map(fold).collect(),
+            ignore_case
+        }
+    }
+
+    /// Whether the entry is selected, every entry is included if no pattern is given.
+    pub fn is_match(&self, path: &Path) -> bool {
+        let name = path.to_string_lossy();
+        let name = if self.ignore_case {
+            Cow::Owned(name.to_lowercase())
+        } else {
+            name
+        };
+
+        let mut included = self.include.is_empty();
+        for (pattern, matched) in self.include.iter() {
+            if wildcard_match(pattern, &name) {
+                matched.store(true, Ordering::Relaxed);
+                included = true;
+            }
+        }
+
+        included && !self.exclude.iter().any(|pattern| wildcard_match(pattern, &name))
+    }
+
+    /// The first include pattern that has not matched any entry.
+    pub fn unmatched(&self) -> Option<&str> {
+        self.include.iter()
+            .find(|(_, matched)| !matched.load(Ordering::Relaxed))
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+/// Match the name with wildcards like Info-ZIP unzip.
+///
+/// `*` matches any chars including `/`, `?` matches one char,
+/// `[...]` matches one char in the set, `[!...]` or `[^...]` one char not in it.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where to retry if the chars after the last star do not match
+    let mut backtrack = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue
+            },
+            Some('?') => Some(1),
+            Some('[') => match match_set(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                // not a set, match it literally
+                None => (name[n] == '[').then_some(1)
+            },
+            Some(&c) => (name[n] == c).then_some(1),
+            None => None
+        };
+
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            },
+            (None, Some((star_p, star_n))) => {
+                // let the star match one more char
+                backtrack = Some((star_p, star_n + 1));
+                p = star_p;
+                n = star_n + 1;
+            },
+            (None, None) => return false
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` with the set at the start of pattern,
+/// return whether it matches and the length of the set, or `None` if the set is not closed.
+fn match_set(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        match pattern.get(i)? {
+            // `]` is literal at the start of set
+            ']' if !first => return Some((matched != negate, i + 1)),
+            &lo => match pattern.get(i + 1..i + 3) {
+                Some(&['-', hi]) if hi != ']' => {
+                    matched |= (lo..=hi).contains(&c);
+                    i += 3;
+                },
+                _ => {
+                    matched |= lo == c;
+                    i += 1;
+                }
+            }
+        }
+
+        first = false;
+    }
+}
+
 #[cfg(unix)]
 pub fn sanitize_setuid(input: std::fs::Permissions) -> std::fs::Permissions {
     use std::os::unix::fs::PermissionsExt;
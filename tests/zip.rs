@@ -256,3 +256,2183 @@ fn test_evil_path2() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_zip64_local_header() -> anyhow::Result<()> {
+    use zip::write::FileOptions;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test6.zip");
+
+    // create zip
+    {
+        let fd = fs::File::create(&path)?;
+        let mut writer = ZipWriter::new(fd);
+
+        let options = FileOptions::default().large_file(true);
+        writer.start_file("Cargo.toml", options)?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+
+        writer.finish()?;
+    }
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("Cargo.toml"))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_zip64_eocdr() -> anyhow::Result<()> {
+    use zip::write::FileOptions;
+    use zip_parser::ZipArchive;
+
+    const COUNT: u64 = u16::MAX as u64 + 1;
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+
+    for i in 0..COUNT {
+        writer.start_file(i.to_string(), options)?;
+    }
+
+    let buf = writer.finish()?.into_inner();
+
+    let zip = ZipArchive::parse(&buf)?;
+    assert_eq!(zip.eocdr().cd_entries, COUNT);
+
+    let mut count = 0;
+    for (i, cfh) in zip.entries()?.enumerate() {
+        let cfh = cfh?;
+        assert_eq!(cfh.name, i.to_string().as_bytes());
+        count += 1;
+    }
+    assert_eq!(count, COUNT);
+
+    // the recorded offset of zip64 eocdr is wrong if data is prepended
+    let mut buf2 = b"#!/bin/sh\nexit 0\n".to_vec();
+    buf2.extend_from_slice(&buf);
+
+    let zip = ZipArchive::parse(&buf2)?;
+    assert_eq!(zip.prefix_len(), buf2.len() as u64 - buf.len() as u64);
+    assert_eq!(zip.entries()?.count() as u64, COUNT);
+
+    let cfh = zip.entries()?.next_back().unwrap()?;
+    let (lfh, _) = zip.read(&cfh)?;
+    assert_eq!(lfh.name, (COUNT - 1).to_string().as_bytes());
+
+    Ok(())
+}
+
+#[test]
+fn test_extra_fields() -> anyhow::Result<()> {
+    use zip_parser::extra::{ self, ExtraFields, ExtendedTimestamp, InfoZipUnix, Unicode };
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&extra::id::EXTENDED_TIMESTAMP.to_le_bytes());
+    buf.extend_from_slice(&5u16.to_le_bytes());
+    buf.push(0b11);
+    buf.extend_from_slice(&1_600_000_000i32.to_le_bytes());
+    buf.extend_from_slice(&extra::id::INFOZIP_UNIX.to_le_bytes());
+    buf.extend_from_slice(&11u16.to_le_bytes());
+    buf.extend_from_slice(&[1, 4]);
+    buf.extend_from_slice(&1000u32.to_le_bytes());
+    buf.push(4);
+    buf.extend_from_slice(&100u32.to_le_bytes());
+    buf.extend_from_slice(&extra::id::UNICODE_PATH.to_le_bytes());
+    buf.extend_from_slice(&8u16.to_le_bytes());
+    buf.push(1);
+    buf.extend_from_slice(&0x12345678u32.to_le_bytes());
+    buf.extend_from_slice(b"abc");
+
+    let fields = ExtraFields::new(&buf).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(fields.len(), 3);
+
+    let (id, data) = fields[0];
+    assert_eq!(id, extra::id::EXTENDED_TIMESTAMP);
+    let ts = ExtendedTimestamp::parse(data)?;
+    assert_eq!(ts.mtime, Some(1_600_000_000));
+    assert_eq!(ts.atime, None);
+
+    let (id, data) = fields[1];
+    assert_eq!(id, extra::id::INFOZIP_UNIX);
+    let ux = InfoZipUnix::parse(data)?;
+    assert_eq!((ux.uid, ux.gid), (1000, 100));
+
+    let (id, data) = fields[2];
+    assert_eq!(id, extra::id::UNICODE_PATH);
+    let up = Unicode::parse(data)?;
+    assert_eq!(up.crc32, 0x12345678);
+    assert_eq!(up.value, b"abc");
+
+    // truncated field
+    let mut fields = ExtraFields::new(&buf[..buf.len() - 1]);
+    assert!(fields.next().unwrap().is_ok());
+    assert!(fields.next().unwrap().is_ok());
+    assert!(fields.next().unwrap().is_err());
+    assert!(fields.next().is_none());
+
+    Ok(())
+}
+
+/// A single entry whose sizes and crc are deferred to a data descriptor.
+fn descriptor_zip(name: &str, data: &[u8], method: u16, descriptor_crc: u32) -> Vec<u8> {
+    let crc = crc32fast::hash(data);
+    let size = data.len() as u32;
+    let comp_data = match method {
+        0 => data.to_vec(),
+        8 => {
+            use std::io::Write;
+
+            let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        },
+        #[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+        93 => zstd_compress(data),
+        #[cfg(feature = "bzip2")]
+        12 => {
+            use std::io::Write;
+
+            let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        },
+        #[cfg(feature = "lzma")]
+        14 => lzma_compress(data),
+        #[cfg(feature = "lzma")]
+        95 => {
+            use std::io::Write;
+
+            let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        },
+        _ => unreachable!()
+    };
+    // lzma of unknown size is terminated by end of stream marker
+    let gp_flag: u16 = if method == 14 { 1 << 3 | 1 << 1 } else { 1 << 3 };
+    let comp_size = comp_data.len() as u32;
+    let mut buf = Vec::new();
+
+    // local file header
+    buf.extend_from_slice(b"PK\x03\x04");
+    buf.extend_from_slice(&20u16.to_le_bytes());
+    buf.extend_from_slice(&gp_flag.to_le_bytes());
+    buf.extend_from_slice(&method.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&0x21u16.to_le_bytes());
+    buf.extend_from_slice(&[0; 4 + 4 + 4]);
+    buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(&comp_data);
+
+    // data descriptor
+    buf.extend_from_slice(b"PK\x07\x08");
+    buf.extend_from_slice(&descriptor_crc.to_le_bytes());
+    buf.extend_from_slice(&comp_size.to_le_bytes());
+    buf.extend_from_slice(&size.to_le_bytes());
+
+    // central file header
+    let cd_offset = buf.len() as u32;
+    buf.extend_from_slice(b"PK\x01\x02");
+    buf.extend_from_slice(&20u16.to_le_bytes());
+    buf.extend_from_slice(&20u16.to_le_bytes());
+    buf.extend_from_slice(&gp_flag.to_le_bytes());
+    buf.extend_from_slice(&method.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&0x21u16.to_le_bytes());
+    buf.extend_from_slice(&crc.to_le_bytes());
+    buf.extend_from_slice(&comp_size.to_le_bytes());
+    buf.extend_from_slice(&size.to_le_bytes());
+    buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&[0; 2 + 2 + 2 + 2 + 4 + 4]);
+    buf.extend_from_slice(name.as_bytes());
+    let cd_size = buf.len() as u32 - cd_offset;
+
+    // end of central directory record
+    buf.extend_from_slice(b"PK\x05\x06");
+    buf.extend_from_slice(&[0; 2 + 2]);
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&cd_size.to_le_bytes());
+    buf.extend_from_slice(&cd_offset.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+
+    buf
+}
+
+#[test]
+fn test_data_descriptor() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let data = b"hello data descriptor";
+
+    let path = dir.join("test7.zip");
+    fs::write(&path, descriptor_zip("hello.txt", data, 0, crc32fast::hash(data)))?;
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("hello.txt"))?, data);
+
+    let path = dir.join("test8.zip");
+    fs::write(&path, descriptor_zip("hello2.txt", data, 0, 0))?;
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("data descriptor does not match"));
+
+    Ok(())
+}
+
+#[test]
+fn test_stream() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    // create zip
+    let buf = {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+
+        writer.start_file("Cargo.toml", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+
+        writer.add_directory("lock/", Default::default())?;
+        writer.start_file("lock/Cargo.lock", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.lock")?, &mut writer)?;
+
+        writer.finish()?.into_inner()
+    };
+
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-d")
+        .arg(dir)
+        .write_stdin(buf)
+        .assert()
+        .success();
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("Cargo.toml"))?);
+    assert_eq!(hash_file(Path::new("Cargo.lock"))?, hash_file(&dir.join("lock/Cargo.lock"))?);
+
+    // deferred sizes
+    let data = fs::read("Cargo.toml")?;
+    let buf = descriptor_zip("deferred.toml", &data, 8, crc32fast::hash(&data));
+
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-d")
+        .arg(dir)
+        .write_stdin(buf)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("deferred.toml"))?, data);
+
+    let buf = descriptor_zip("deferred2.toml", &data, 8, 0);
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-d")
+        .arg(dir)
+        .write_stdin(buf)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("crc32 check failed"));
+
+    // the sizes in data descriptor or central directory are wrong
+    let buf = descriptor_zip("deferred3.toml", &data, 8, crc32fast::hash(&data));
+    let dd_pos = buf.windows(4).position(|window| window == b"PK\x07\x08").unwrap();
+    let cd_pos = buf.windows(4).position(|window| window == b"PK\x01\x02").unwrap();
+
+    for (i, (pos, msg)) in [
+        (dd_pos + 8, "data descriptor does not match data"),
+        (dd_pos + 12, "data descriptor does not match data"),
+        (cd_pos + 20, "sizes do not match central directory"),
+        (cd_pos + 24, "sizes do not match central directory")
+    ].into_iter().enumerate() {
+        let mut buf = buf.clone();
+        buf[pos] ^= 1;
+
+        let assert = Command::cargo_bin("unzrip")?
+            .arg("-")
+            .arg("-d")
+            .arg(dir.join(format!("wrong{}", i)))
+            .write_stdin(buf)
+            .assert()
+            .failure();
+        assert!(assert.get_output().stderr.contains_str(msg));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_recover() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test9.zip");
+
+    // create zip, then cut off the central directory
+    {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+
+        writer.start_file("Cargo.toml", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+
+        writer.add_directory("lock/", Default::default())?;
+        writer.start_file("lock/Cargo.lock", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.lock")?, &mut writer)?;
+
+        let cd_offset = |buf: &[u8]| buf.windows(4)
+            .position(|sig| sig == b"PK\x01\x02")
+            .unwrap();
+
+        let mut buf = writer.finish()?.into_inner();
+        buf.truncate(cd_offset(&buf));
+
+        let data = fs::read("Cargo.toml")?;
+        let buf2 = descriptor_zip("deferred.toml", &data, 8, crc32fast::hash(&data));
+        buf.extend_from_slice(&buf2[..cd_offset(&buf2) + 10]);
+
+        fs::write(&path, buf)?;
+    }
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("--recover")
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+    assert!(assert.get_output().stdout.contains_str("recovered: lock/Cargo.lock"));
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("Cargo.toml"))?);
+    assert_eq!(hash_file(Path::new("Cargo.lock"))?, hash_file(&dir.join("lock/Cargo.lock"))?);
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("deferred.toml"))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_recover_corrupted_size() -> anyhow::Result<()> {
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let data = fs::read("Cargo.toml")?;
+
+    for (i, method) in [zip::CompressionMethod::Deflated, zip::CompressionMethod::Stored].into_iter().enumerate() {
+        let path = dir.join(format!("test29-{}.zip", i));
+        let target = dir.join(format!("out{}", i));
+
+        // truncate central directory and corrupt the compressed size of first entry,
+        // so that it covers the following entries.
+        {
+            let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+            let options = FileOptions::default().compression_method(method);
+
+            for n in 0..4 {
+                writer.start_file(format!("f{}", n), options)?;
+                writer.write_all(&data)?;
+            }
+
+            let mut buf = writer.finish()?.into_inner();
+            let cd_offset = buf.windows(4)
+                .position(|sig| sig == b"PK\x01\x02")
+                .unwrap();
+            buf.truncate(cd_offset);
+
+            let comp_size = u32::from_le_bytes(buf[18..][..4].try_into()?);
+            let corrupted = comp_size + 150;
+            buf[18..][..4].copy_from_slice(&corrupted.to_le_bytes());
+            if method == zip::CompressionMethod::Stored {
+                buf[22..][..4].copy_from_slice(&corrupted.to_le_bytes());
+            }
+
+            fs::write(&path, buf)?;
+        }
+
+        let assert = Command::cargo_bin("unzrip")?
+            .arg(&path)
+            .arg("--recover")
+            .arg("-d")
+            .arg(&target)
+            .assert();
+
+        for n in 1..4 {
+            let name = format!("f{}", n);
+            assert!(assert.get_output().stdout.contains_str(format!("recovered: {}", name)));
+            assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&target.join(name))?);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_recover_nested_archive() -> anyhow::Result<()> {
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test34.zip");
+    let target = dir.join("out");
+
+    // the headers of inner archive survive in the stored deflate blocks
+    {
+        let mut inner = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        inner.start_file("secret_inner.bin", options)?;
+        inner.write_all(b"secret")?;
+        inner.start_file("second_inner.bin", options)?;
+        inner.write_all(b"second")?;
+        let inner = inner.finish()?.into_inner();
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("inner.jar", FileOptions::default().compression_level(Some(0)))?;
+        writer.write_all(&inner)?;
+        writer.start_file("Cargo.toml", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+        let buf = writer.finish()?.into_inner();
+
+        assert!(buf.windows(16).any(|name| name == b"secret_inner.bin"));
+        fs::write(&path, buf)?;
+    }
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("--recover")
+        .arg("-d")
+        .arg(&target)
+        .assert()
+        .success();
+
+    let mut extracted = list_dir(&target)?;
+    extracted.sort();
+    assert_eq!(extracted, vec![Path::new("Cargo.toml"), Path::new("inner.jar")]);
+
+    Ok(())
+}
+
+#[test]
+fn test_huge_entry_count() -> anyhow::Result<()> {
+    use zip_parser::ZipArchive;
+
+    const COUNT: u64 = 1 << 62;
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    writer.start_file("Cargo.toml", Default::default())?;
+    io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+    let buf = writer.finish()?.into_inner();
+
+    // replace eocdr with a zip64 eocdr recording a huge number of entries
+    let eocdr_offset = buf.windows(4).position(|sig| sig == b"PK\x05\x06").unwrap();
+    let cd_size = u32::from_le_bytes(buf[eocdr_offset + 12..][..4].try_into()?);
+    let cd_offset = u32::from_le_bytes(buf[eocdr_offset + 16..][..4].try_into()?);
+
+    let mut bomb = buf[..eocdr_offset].to_vec();
+    bomb.extend_from_slice(b"PK\x06\x06");
+    bomb.extend_from_slice(&44u64.to_le_bytes());
+    bomb.extend_from_slice(&[45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    bomb.extend_from_slice(&COUNT.to_le_bytes());
+    bomb.extend_from_slice(&COUNT.to_le_bytes());
+    bomb.extend_from_slice(&u64::from(cd_size).to_le_bytes());
+    bomb.extend_from_slice(&u64::from(cd_offset).to_le_bytes());
+    bomb.extend_from_slice(b"PK\x06\x07");
+    bomb.extend_from_slice(&0u32.to_le_bytes());
+    bomb.extend_from_slice(&(eocdr_offset as u64).to_le_bytes());
+    bomb.extend_from_slice(&1u32.to_le_bytes());
+    bomb.extend_from_slice(b"PK\x05\x06\0\0\0\0\xff\xff\xff\xff");
+    bomb.extend_from_slice(&cd_size.to_le_bytes());
+    bomb.extend_from_slice(&cd_offset.to_le_bytes());
+    bomb.extend_from_slice(&0u16.to_le_bytes());
+
+    let zip = ZipArchive::parse(&bomb)?;
+    assert_eq!(zip.eocdr().cd_entries, COUNT);
+
+    let entries = zip.entries()?;
+    assert!(entries.len() <= (bomb.len() - cd_offset as usize) / 46 + 1);
+
+    let entries = entries.collect::<Vec<_>>();
+    assert!(entries[0].is_ok());
+    assert!(entries[1..].iter().all(Result::is_err));
+    assert_eq!(zip.entries()?.filter_map(Result::ok).count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_overlap() -> anyhow::Result<()> {
+    use zip_parser::{ ZipArchive, Overlap };
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test10.zip");
+
+    // create zip, then point a second central file header at the same data
+    {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+
+        writer.start_file("Cargo.toml", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+
+        let buf = writer.finish()?.into_inner();
+
+        let find = |sig: &[u8]| buf.windows(4).position(|w| w == sig).unwrap();
+        let cd_offset = find(b"PK\x01\x02");
+        let eocdr_offset = find(b"PK\x05\x06");
+
+        let mut cfh = buf[cd_offset..eocdr_offset].to_vec();
+        let name_offset = 46;
+        cfh[name_offset..][..10].copy_from_slice(b"Cargo.tomx");
+
+        let mut bomb = buf[..eocdr_offset].to_vec();
+        bomb.extend_from_slice(&cfh);
+        let mut eocdr = buf[eocdr_offset..].to_vec();
+        eocdr[8..10].copy_from_slice(&2u16.to_le_bytes());
+        eocdr[10..12].copy_from_slice(&2u16.to_le_bytes());
+        eocdr[12..16].copy_from_slice(&((cfh.len() * 2) as u32).to_le_bytes());
+        bomb.extend_from_slice(&eocdr);
+
+        let zip = ZipArchive::parse(&bomb)?;
+        let overlaps = zip.find_overlaps()?;
+        assert_eq!(overlaps.len(), 1);
+        assert!(matches!(overlaps[0], Overlap::Entry { offset: 0, other: 0 }));
+        assert!(zip.check_overlap().is_err());
+
+        fs::write(&path, bomb)?;
+    }
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("overlapped entry"));
+    assert!(!dir.join("Cargo.toml").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_prepended_data() -> anyhow::Result<()> {
+    use zip_parser::ZipArchive;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test11.zip");
+
+    // self-extracting archive, the offsets are relative to the start of zip
+    let mut buf = b"#!/bin/sh\necho self-extracting\nexit 0\n".to_vec();
+    {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+
+        writer.start_file("Cargo.toml", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+
+        writer.add_directory("lock/", Default::default())?;
+        writer.start_file("lock/Cargo.lock", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.lock")?, &mut writer)?;
+
+        let zip = writer.finish()?.into_inner();
+
+        let prefix_len = buf.len() as u64;
+        buf.extend_from_slice(&zip);
+
+        let zip = ZipArchive::parse(&buf)?;
+        assert_eq!(zip.prefix_len(), prefix_len);
+        zip.check_overlap()?;
+
+        fs::write(&path, buf)?;
+    }
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("Cargo.toml"))?);
+    assert_eq!(hash_file(Path::new("Cargo.lock"))?, hash_file(&dir.join("lock/Cargo.lock"))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_index() -> anyhow::Result<()> {
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip_parser::ZipArchive;
+
+    const COUNT: usize = 1000;
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    let options = FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+
+    for i in 0..COUNT {
+        writer.start_file(i.to_string(), options)?;
+        writer.write_all(i.to_string().as_bytes())?;
+    }
+
+    let buf = writer.finish()?.into_inner();
+
+    let mut zip = ZipArchive::parse(&buf)?;
+    assert_eq!(zip.len(), COUNT);
+    assert_eq!(zip.entries()?.len(), COUNT);
+
+    for indexed in [false, true] {
+        if indexed {
+            zip.build_index()?;
+        }
+
+        let cfh = zip.by_name(b"123")?.unwrap();
+        let (_, data) = zip.read(&cfh)?;
+        assert_eq!(data, b"123");
+
+        let cfh = zip.by_index(456)?.unwrap();
+        assert_eq!(cfh.name, b"456");
+
+        assert!(zip.by_name(b"1000")?.is_none());
+        assert!(zip.by_index(COUNT)?.is_none());
+
+        let mut entries = zip.entries()?;
+        assert_eq!(entries.next().unwrap()?.name, b"0");
+        assert_eq!(entries.next_back().unwrap()?.name, b"999");
+        assert_eq!(entries.len(), COUNT - 2);
+
+        let names = entries.rev()
+            .map(|cfh| cfh.map(|cfh| cfh.name.to_vec()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let expect = (1..COUNT - 1).rev()
+            .map(|i| i.to_string().into_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(names, expect);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_writer() -> anyhow::Result<()> {
+    use std::io::{ Read, Write };
+    use flate2::{ Compression, write::DeflateEncoder };
+    use zip_parser::{ compress, ZipArchive };
+    use zip_parser::write::{ ZipWriter, FileOptions };
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test12.zip");
+
+    {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default()
+            .mod_time(0, 0x21)
+            .unix_mode(0o100644);
+
+        writer.start_file(b"Cargo.toml", &options)?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+
+        writer.add_directory(b"lock", &options)?;
+
+        let data = fs::read("Cargo.lock")?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data)?;
+        let comp_data = encoder.finish()?;
+        writer.add_raw_file(
+            b"lock/Cargo.lock",
+            &options,
+            compress::DEFLATE,
+            crc32fast::hash(&data),
+            data.len() as u64,
+            &comp_data
+        )?;
+
+        writer.start_file(b"large", &options.clone().large_file(true))?;
+        writer.write_all(b"large file")?;
+
+        let buf = writer.finish()?.into_inner();
+        fs::write(&path, buf)?;
+    }
+
+    // readable by the other implementation
+    {
+        let mut zip = zip::ZipArchive::new(fs::File::open(&path)?)?;
+        assert_eq!(zip.len(), 4);
+
+        let mut buf = Vec::new();
+        zip.by_name("large")?.read_to_end(&mut buf)?;
+        assert_eq!(buf, b"large file");
+        assert_eq!(zip.by_name("Cargo.toml")?.unix_mode(), Some(0o100644));
+        assert!(zip.by_name("lock/")?.is_dir());
+    }
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("Cargo.toml"))?);
+    assert_eq!(hash_file(Path::new("Cargo.lock"))?, hash_file(&dir.join("lock/Cargo.lock"))?);
+
+    // zip64 eocdr
+    {
+        const COUNT: u64 = u16::MAX as u64;
+
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for i in 0..COUNT {
+            writer.add_raw_file(i.to_string().as_bytes(), &FileOptions::default(), compress::STORE, 0, 0, &[])?;
+        }
+        let buf = writer.finish()?.into_inner();
+
+        let zip = ZipArchive::parse(&buf)?;
+        assert_eq!(zip.eocdr().cd_entries, COUNT);
+        assert_eq!(zip::ZipArchive::new(io::Cursor::new(&buf))?.len() as u64, COUNT);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reader() -> anyhow::Result<()> {
+    use std::io::{ Read, Write };
+    use zip::write::FileOptions;
+    use zip_parser::ZipArchive;
+    use unzrip::ZipArchiveExt;
+
+    let data = fs::read("Cargo.lock")?;
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    writer.start_file("deflate", Default::default())?;
+    writer.write_all(&data)?;
+    writer.start_file("stored", FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored))?;
+    writer.write_all(b"hello world")?;
+    let mut buf = writer.finish()?.into_inner();
+
+    {
+        let zip = ZipArchive::parse(&buf)?;
+
+        let mut output = Vec::new();
+        let cfh = zip.by_name(b"deflate")?.unwrap();
+        zip.reader(&cfh)?.read_to_end(&mut output)?;
+        assert_eq!(output, data);
+
+        let mut output = vec![0; data.len()];
+        assert!(zip.inflate_into(&cfh, &mut output)?);
+        assert_eq!(output, data);
+
+        let cfh = zip.by_name(b"stored")?.unwrap();
+        assert!(!zip.inflate_into(&cfh, &mut [0; 11])?);
+    }
+
+    // corrupt the stored data
+    let pos = buf.windows(11).position(|window| window == b"hello world").unwrap();
+    buf[pos] = b'j';
+
+    let zip = ZipArchive::parse(&buf)?;
+    let cfh = zip.by_name(b"stored")?.unwrap();
+    let err = zip.reader(&cfh)?.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+#[test]
+fn test_inflate_at_once() -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let data = fs::read("Cargo.toml")?;
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Default::default());
+    encoder.write_all(&data)?;
+    let comp_data = encoder.finish()?;
+    let crc = crc32fast::hash(&data);
+
+    let path = dir.join("test23.zip");
+    fs::write(&path, raw_zip("small.toml", 0, 8, crc, data.len() as u32, &comp_data, &[]))?;
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("small.toml"))?, data);
+
+    // the recorded size is wrong
+    for size in [data.len() - 1, data.len() + 1] {
+        fs::write(&path, raw_zip("wrong.toml", 0, 8, crc, size as u32, &comp_data, &[]))?;
+
+        Command::cargo_bin("unzrip")?
+            .arg(&path)
+            .arg("-d")
+            .arg(dir)
+            .assert()
+            .failure();
+
+        assert!(!dir.join("wrong.toml").exists());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_list() -> anyhow::Result<()> {
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test24.zip");
+
+    {
+        let fd = fs::File::create(&path)?;
+        let mut writer = ZipWriter::new(fd);
+
+        writer.add_directory("dir/", Default::default())?;
+        writer.start_file("dir/hello.txt", FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored))?;
+        writer.write_all(b"hello world")?;
+        writer.start_file("Cargo.toml", Default::default())?;
+        io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+
+        writer.finish()?;
+    }
+
+    let size = fs::metadata("Cargo.toml")?.len() + 11;
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-l")
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+    let stdout = &assert.get_output().stdout;
+    assert!(stdout.contains_str("       11  1980-01-01 00:00   dir/hello.txt\n"));
+    assert!(stdout.contains_str(format!("{:>9}                     3 files\n", size)));
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-v")
+        .arg(&path)
+        .assert()
+        .success();
+    let stdout = &assert.get_output().stdout;
+    assert!(stdout.contains_str("      11  Stored       11   0% 1980-01-01 00:00 0d4a1185  dir/hello.txt\n"));
+    assert!(stdout.contains_str("  Defl:N  "));
+
+    // nothing is extracted
+    assert_eq!(list_dir(dir)?, vec![Path::new("test24.zip")]);
+
+    Ok(())
+}
+
+#[test]
+fn test_broken_pipe() -> anyhow::Result<()> {
+    use std::process::Stdio;
+    use assert_cmd::cargo::CommandCargoExt;
+    use zip::write::FileOptions;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test31.zip");
+
+    // output is much larger than the pipe buffer
+    {
+        let fd = fs::File::create(&path)?;
+        let mut writer = ZipWriter::new(fd);
+        let options = FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+
+        for i in 0..10000 {
+            writer.start_file(i.to_string(), options)?;
+        }
+
+        writer.finish()?;
+    }
+
+    // the reader goes away, like `unzrip -l test31.zip | head`
+    for mode in ["-l", "-t"] {
+        let mut child = std::process::Command::cargo_bin("unzrip")?
+            .arg(mode)
+            .arg(&path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        drop(child.stdout.take());
+
+        let output = child.wait_with_output()?;
+        assert!(output.status.success(), "{}", mode);
+        assert!(output.stderr.is_empty(), "{}: {}", mode, output.stderr.as_bstr());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_test_mode() -> anyhow::Result<()> {
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test25.zip");
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    writer.start_file("Cargo.toml", Default::default())?;
+    io::copy(&mut fs::File::open("Cargo.toml")?, &mut writer)?;
+    writer.start_file("hello.txt", FileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored))?;
+    writer.write_all(b"hello world")?;
+    let mut buf = writer.finish()?.into_inner();
+    fs::write(&path, &buf)?;
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-t")
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+    let stdout = &assert.get_output().stdout;
+    assert!(stdout.contains_str("testing: Cargo.toml   OK"));
+    assert!(stdout.contains_str("testing: hello.txt   OK"));
+    assert!(stdout.contains_str("No errors detected"));
+
+    // corrupt the stored data
+    let pos = buf.windows(11).position(|window| window == b"hello world").unwrap();
+    buf[pos] = b'j';
+    fs::write(&path, &buf)?;
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-t")
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+    let output = assert.get_output();
+    assert!(output.stdout.contains_str("testing: Cargo.toml   OK"));
+    assert!(output.stdout.contains_str("testing: hello.txt   FAIL"));
+    assert!(output.stderr.contains_str("crc32 check failed"));
+
+    // nothing is written
+    assert_eq!(list_dir(dir)?, vec![Path::new("test25.zip")]);
+
+    Ok(())
+}
+
+#[test]
+fn test_pipe() -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test26.zip");
+
+    let toml = fs::read("Cargo.toml")?;
+    let lock = fs::read("Cargo.lock")?;
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    writer.start_file("hello.txt", Default::default())?;
+    writer.write_all(b"hello world")?;
+    writer.add_directory("dir/", Default::default())?;
+    writer.start_file("dir/Cargo.toml", Default::default())?;
+    writer.write_all(&toml)?;
+    writer.start_file("dir/Cargo.lock", Default::default())?;
+    writer.write_all(&lock)?;
+    let buf = writer.finish()?.into_inner();
+    fs::write(&path, &buf)?;
+
+    // in central directory order
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-p")
+        .arg(&path)
+        .arg("dir/Cargo.l?ck")
+        .arg("dir/*.[a-z]oml")
+        .assert()
+        .success();
+    assert_eq!(assert.get_output().stdout, [toml.as_slice(), lock.as_slice()].concat());
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-p")
+        .arg("-")
+        .arg("hello.txt")
+        .write_stdin(buf)
+        .assert()
+        .success();
+    assert_eq!(assert.get_output().stdout, b"hello world");
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg("-p")
+        .arg(&path)
+        .arg("hello.txt")
+        .arg("missing.txt")
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("filename not matched: missing.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_filter() -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test27.zip");
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    for name in ["sdk/lib/a.so", "sdk/lib/b.txt", "sdk/bin/c.so", "SDK/LIB/D.SO"] {
+        writer.start_file(name, Default::default())?;
+        writer.write_all(name.as_bytes())?;
+    }
+    fs::write(&path, writer.finish()?.into_inner())?;
+
+    let extracted = |dir: &Path| ["sdk/lib/a.so", "sdk/lib/b.txt", "sdk/bin/c.so", "SDK/LIB/D.SO"]
+        .into_iter()
+        .filter(|name| dir.join(name).exists())
+        .collect::<Vec<_>>();
+
+    let dir1 = dir.join("1");
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("*/lib/*.so")
+        .arg("-d")
+        .arg(&dir1)
+        .assert()
+        .success();
+    assert_eq!(extracted(&dir1), ["sdk/lib/a.so"]);
+    assert_eq!(fs::read(dir1.join("sdk/lib/a.so"))?, b"sdk/lib/a.so");
+
+    let dir2 = dir.join("2");
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-C")
+        .arg("*/lib/*")
+        .arg("-x")
+        .arg("*.txt")
+        .arg("-d")
+        .arg(&dir2)
+        .assert()
+        .success();
+    assert_eq!(extracted(&dir2), ["sdk/lib/a.so", "SDK/LIB/D.SO"]);
+
+    let dir3 = dir.join("3");
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("*.so")
+        .arg("-d")
+        .arg(&dir3)
+        .write_stdin(fs::read(&path)?)
+        .assert()
+        .success();
+    assert_eq!(extracted(&dir3), ["sdk/lib/a.so", "sdk/bin/c.so"]);
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("sdk/lib/a.so")
+        .arg("missing.so")
+        .arg("-d")
+        .arg(dir.join("4"))
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("filename not matched: missing.so"));
+
+    // the skipped entry of unknown size must be read through
+    let data = fs::read("Cargo.toml")?;
+    let buf = descriptor_zip("deferred.toml", &data, 8, crc32fast::hash(&data));
+
+    let dir5 = dir.join("5");
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-x")
+        .arg("deferred.toml")
+        .arg("-d")
+        .arg(&dir5)
+        .write_stdin(buf)
+        .assert()
+        .success();
+    assert!(!dir5.join("deferred.toml").exists());
+
+    // wildcards match chars of the decoded name, not bytes
+    let path = dir.join("test35.zip");
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    for name in ["\u{e9}.txt", "ab.txt", "\u{fc}bung.txt"] {
+        writer.start_file(name, Default::default())?;
+        writer.write_all(name.as_bytes())?;
+    }
+    fs::write(&path, writer.finish()?.into_inner())?;
+
+    let dir6 = dir.join("6");
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("?.txt")
+        .arg("[\u{fc}]*")
+        .arg("-d")
+        .arg(&dir6)
+        .assert()
+        .success();
+    let mut extracted = list_dir(&dir6)?;
+    extracted.sort();
+    assert_eq!(extracted, vec![Path::new("\u{e9}.txt"), Path::new("\u{fc}bung.txt")]);
+
+    Ok(())
+}
+
+#[test]
+fn test_multiple_archives() -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path1 = dir.join("test32.zip");
+    let path2 = dir.join("test33.zip");
+
+    for (path, names) in [(&path1, ["a.so", "a.txt"]), (&path2, ["b.so", "nested.zip"])] {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        for name in names {
+            writer.start_file(name, Default::default())?;
+            writer.write_all(name.as_bytes())?;
+        }
+        fs::write(path, writer.finish()?.into_inner())?;
+    }
+
+    // a wildcard in the archive name selects several archives
+    let dir1 = dir.join("1");
+    Command::cargo_bin("unzrip")?
+        .arg(dir.join("test3[23].zip"))
+        .arg("*.so")
+        .arg("-d")
+        .arg(&dir1)
+        .assert()
+        .success();
+    let mut extracted = list_dir(&dir1)?;
+    extracted.sort();
+    assert_eq!(extracted, vec![Path::new("a.so"), Path::new("b.so")]);
+
+    // every argument after the archive is a name, even an existing zip file
+    let dir2 = dir.join("2");
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path1)
+        .arg(&path2)
+        .arg("-d")
+        .arg(&dir2)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("filename not matched"));
+
+    let dir3 = dir.join("3");
+    Command::cargo_bin("unzrip")?
+        .arg(&path2)
+        .arg("nested.zip")
+        .arg("-d")
+        .arg(&dir3)
+        .assert()
+        .success();
+    assert_eq!(list_dir(&dir3)?, vec![Path::new("nested.zip")]);
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(dir.join("missing*.zip"))
+        .arg("-d")
+        .arg(dir.join("4"))
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("archive not matched"));
+
+    Ok(())
+}
+
+#[test]
+fn test_overwrite() -> anyhow::Result<()> {
+    use std::io::Write;
+    use zip::{ DateTime, write::FileOptions };
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test28.zip");
+    let out = dir.join("out");
+
+    let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+    for (name, year) in [("old.txt", 2000), ("new.txt", 2030), ("missing.txt", 2000)] {
+        let options = FileOptions::default()
+            .last_modified_time(DateTime::from_date_and_time(year, 1, 1, 0, 0, 0).unwrap());
+        writer.start_file(name, options)?;
+        writer.write_all(b"archive")?;
+    }
+    writer.add_directory("dir/", Default::default())?;
+    fs::write(&path, writer.finish()?.into_inner())?;
+
+    let unzip = |flag: Option<&str>| -> anyhow::Result<_> {
+        let mut cmd = Command::cargo_bin("unzrip")?;
+        cmd.arg(&path).arg("-d").arg(&out);
+        cmd.args(flag);
+        Ok(cmd.assert())
+    };
+
+    // local files modified in 2010
+    let reset = || -> anyhow::Result<()> {
+        let mtime = filetime::FileTime::from_unix_time(1_262_304_000, 0);
+        for name in ["old.txt", "new.txt"] {
+            fs::write(out.join(name), b"local")?;
+            filetime::set_file_mtime(out.join(name), mtime)?;
+        }
+        for name in ["missing.txt", "dir"] {
+            if out.join(name).is_dir() {
+                fs::remove_dir(out.join(name))?;
+            } else if out.join(name).exists() {
+                fs::remove_file(out.join(name))?;
+            }
+        }
+        Ok(())
+    };
+    let read = |name: &str| fs::read(out.join(name)).unwrap_or_default();
+
+    unzip(None)?.success();
+    reset()?;
+
+    let assert = unzip(None)?.failure();
+    assert!(assert.get_output().stderr.contains_str("old.txt"));
+    reset()?;
+
+    unzip(Some("-n"))?.success();
+    assert_eq!(read("old.txt"), b"local");
+    assert_eq!(read("new.txt"), b"local");
+    assert_eq!(read("missing.txt"), b"archive");
+    assert!(out.join("dir").is_dir());
+    reset()?;
+
+    unzip(Some("-u"))?.success();
+    assert_eq!(read("old.txt"), b"local");
+    assert_eq!(read("new.txt"), b"archive");
+    assert_eq!(read("missing.txt"), b"archive");
+    assert!(out.join("dir").is_dir());
+    reset()?;
+
+    unzip(Some("-f"))?.success();
+    assert_eq!(read("old.txt"), b"local");
+    assert_eq!(read("new.txt"), b"archive");
+    assert!(!out.join("missing.txt").exists());
+    assert!(!out.join("dir").exists());
+
+    // nothing to freshen again
+    let assert = unzip(Some("-f"))?.success();
+    assert!(!assert.get_output().stdout.contains_str("inflating"));
+    reset()?;
+
+    unzip(Some("-o"))?.success();
+    assert_eq!(read("old.txt"), b"archive");
+    assert_eq!(read("new.txt"), b"archive");
+    assert_eq!(read("missing.txt"), b"archive");
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-o")
+        .arg("-n")
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("cannot be used together"));
+
+    Ok(())
+}
+
+/// Single stored entry with the given flag, method and extra field.
+fn raw_zip(name: &str, gp_flag: u16, method: u16, crc: u32, size: u32, comp_data: &[u8], extra: &[u8]) -> Vec<u8> {
+    let comp_size = comp_data.len() as u32;
+    let mut buf = Vec::new();
+
+    // local file header
+    buf.extend_from_slice(b"PK\x03\x04");
+    buf.extend_from_slice(&20u16.to_le_bytes());
+    buf.extend_from_slice(&gp_flag.to_le_bytes());
+    buf.extend_from_slice(&method.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&0x21u16.to_le_bytes());
+    buf.extend_from_slice(&crc.to_le_bytes());
+    buf.extend_from_slice(&comp_size.to_le_bytes());
+    buf.extend_from_slice(&size.to_le_bytes());
+    buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(extra);
+    buf.extend_from_slice(comp_data);
+
+    // central file header
+    let cd_offset = buf.len() as u32;
+    buf.extend_from_slice(b"PK\x01\x02");
+    buf.extend_from_slice(&20u16.to_le_bytes());
+    buf.extend_from_slice(&20u16.to_le_bytes());
+    buf.extend_from_slice(&gp_flag.to_le_bytes());
+    buf.extend_from_slice(&method.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&0x21u16.to_le_bytes());
+    buf.extend_from_slice(&crc.to_le_bytes());
+    buf.extend_from_slice(&comp_size.to_le_bytes());
+    buf.extend_from_slice(&size.to_le_bytes());
+    buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&[0; 2 + 2 + 2 + 4 + 4]);
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(extra);
+    let cd_size = buf.len() as u32 - cd_offset;
+
+    // end of central directory record
+    buf.extend_from_slice(b"PK\x05\x06");
+    buf.extend_from_slice(&[0; 2 + 2]);
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&cd_size.to_le_bytes());
+    buf.extend_from_slice(&cd_offset.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+
+    buf
+}
+
+fn zip_crypto_encrypt(password: &[u8], data: &[u8]) -> Vec<u8> {
+    // raw crc32 step, without the pre and post inversion
+    fn crc32(crc: u32, c: u8) -> u32 {
+        let mut hasher = crc32fast::Hasher::new_with_initial(!crc);
+        hasher.update(&[c]);
+        !hasher.finalize()
+    }
+
+    fn update(keys: &mut [u32; 3], c: u8) {
+        keys[0] = crc32(keys[0], c);
+        keys[1] = keys[1].wrapping_add(keys[0] & 0xff).wrapping_mul(134775813).wrapping_add(1);
+        keys[2] = crc32(keys[2], (keys[1] >> 24) as u8);
+    }
+
+    let mut keys = [0x12345678, 0x23456789, 0x34567890];
+    for &c in password {
+        update(&mut keys, c);
+    }
+
+    let mut header = [0x5a; 12];
+    header[11] = (crc32fast::hash(data) >> 24) as u8;
+
+    header.iter()
+        .chain(data)
+        .map(|&c| {
+            let temp = (keys[2] | 2) as u16;
+            let output = c ^ (temp.wrapping_mul(temp ^ 1) >> 8) as u8;
+            update(&mut keys, c);
+            output
+        })
+        .collect()
+}
+
+#[test]
+fn test_zip_crypto() -> anyhow::Result<()> {
+    use std::io::Read;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test13.zip");
+    let password_path = dir.join("password.txt");
+
+    let data = fs::read("Cargo.toml")?;
+    let comp_data = zip_crypto_encrypt(b"secret", &data);
+    let buf = raw_zip("Cargo.toml", 1, 0, crc32fast::hash(&data), data.len() as u32, &comp_data, &[]);
+    fs::write(&path, buf)?;
+    fs::write(&password_path, "secret\n")?;
+
+    // the archive is readable by the other implementation
+    let mut zip = zip::ZipArchive::new(fs::File::open(&path)?)?;
+    let mut buf = Vec::new();
+    zip.by_name_decrypt("Cargo.toml", b"secret")?.unwrap().read_to_end(&mut buf)?;
+    assert_eq!(buf, data);
+    assert!(zip.by_name_decrypt("Cargo.toml", b"wrong")?.is_err());
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("password is required"));
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-P")
+        .arg("wrong")
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("invalid password"));
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("--password-file")
+        .arg(&password_path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("Cargo.toml"))?);
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir.join("env"))
+        .env("UNZRIP_PASSWORD", "secret")
+        .assert()
+        .success();
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("env/Cargo.toml"))?);
+
+    Ok(())
+}
+
+#[cfg(feature = "aes-crypto")]
+fn winzip_aes_encrypt(password: &[u8], strength: u8, data: &[u8]) -> Vec<u8> {
+    use ctr::cipher::{ KeyIvInit, StreamCipher };
+    use hmac::{ Hmac, Mac };
+
+    let key_len = 8 + 8 * strength as usize;
+    let salt = vec![0x5a; key_len / 2];
+    let mut key = vec![0; key_len * 2 + 2];
+    pbkdf2::pbkdf2_hmac::<sha1::Sha1>(password, &salt, 1000, &mut key);
+
+    let mut nonce = [0; 16];
+    nonce[0] = 1;
+    let mut ciphertext = data.to_vec();
+    match strength {
+        1 => ctr::Ctr128LE::<aes::Aes128>::new(key[..16].into(), &nonce.into()).apply_keystream(&mut ciphertext),
+        3 => ctr::Ctr128LE::<aes::Aes256>::new(key[..32].into(), &nonce.into()).apply_keystream(&mut ciphertext),
+        _ => unreachable!()
+    }
+
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(&key[key_len..][..key_len]).unwrap();
+    mac.update(&ciphertext);
+    let code = mac.finalize().into_bytes();
+
+    let mut buf = salt;
+    buf.extend_from_slice(&key[key_len * 2..]);
+    buf.extend_from_slice(&ciphertext);
+    buf.extend_from_slice(&code[..10]);
+    buf
+}
+
+#[test]
+#[cfg(feature = "aes-crypto")]
+fn test_winzip_aes() -> anyhow::Result<()> {
+    use std::io::{ Read, Write };
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let data = fs::read("Cargo.toml")?;
+    let crc = crc32fast::hash(&data);
+    let size = data.len() as u32;
+
+    let aes_extra = |version: u16, strength: u8, method: u16| {
+        let mut extra = Vec::new();
+        extra.extend_from_slice(&0x9901u16.to_le_bytes());
+        extra.extend_from_slice(&7u16.to_le_bytes());
+        extra.extend_from_slice(&version.to_le_bytes());
+        extra.extend_from_slice(b"AE");
+        extra.push(strength);
+        extra.extend_from_slice(&method.to_le_bytes());
+        extra
+    };
+
+    // AE-1, AES-128, stored
+    let path1 = dir.join("test14.zip");
+    let comp_data = winzip_aes_encrypt(b"secret", 1, &data);
+    fs::write(&path1, raw_zip("ae1.toml", 1, 99, crc, size, &comp_data, &aes_extra(1, 1, 0)))?;
+
+    // AE-2, AES-256, deflate, crc is zero
+    let path2 = dir.join("test15.zip");
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Default::default());
+    encoder.write_all(&data)?;
+    let comp_data = winzip_aes_encrypt(b"secret", 3, &encoder.finish()?);
+    fs::write(&path2, raw_zip("ae2.toml", 1, 99, 0, size, &comp_data, &aes_extra(2, 3, 8)))?;
+
+    // the archives are readable by the other implementation
+    for (path, name) in [(&path1, "ae1.toml"), (&path2, "ae2.toml")] {
+        let mut zip = zip::ZipArchive::new(fs::File::open(path)?)?;
+        let mut buf = Vec::new();
+        zip.by_name_decrypt(name, b"secret")?.unwrap().read_to_end(&mut buf)?;
+        assert_eq!(buf, data);
+    }
+
+    for path in [&path1, &path2] {
+        let assert = Command::cargo_bin("unzrip")?
+            .arg(path)
+            .arg("-P")
+            .arg("wrong")
+            .arg("-d")
+            .arg(dir)
+            .assert()
+            .failure();
+        assert!(assert.get_output().stderr.contains_str("invalid password"));
+
+        Command::cargo_bin("unzrip")?
+            .arg(path)
+            .arg("-P")
+            .arg("secret")
+            .arg("-d")
+            .arg(dir)
+            .assert()
+            .success();
+    }
+
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("ae1.toml"))?);
+    assert_eq!(hash_file(Path::new("Cargo.toml"))?, hash_file(&dir.join("ae2.toml"))?);
+
+    // tampered ciphertext fails the authentication,
+    // even if the decoder does not read it
+    let mut buf = fs::read(&path2)?;
+    let end = buf.windows(4).position(|sig| sig == b"PK\x01\x02").unwrap();
+    buf[end - 10 - 1] ^= 1;
+    fs::write(&path2, buf)?;
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path2)
+        .arg("-P")
+        .arg("secret")
+        .arg("-d")
+        .arg(dir.join("tampered"))
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("authentication"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "bzip2")]
+fn test_bzip2() -> anyhow::Result<()> {
+    use zip::write::FileOptions;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test16.zip");
+
+    {
+        let fd = fs::File::create(&path)?;
+        let mut writer = ZipWriter::new(fd);
+
+        writer.start_file("Cargo.lock", FileOptions::default()
+            .compression_method(zip::CompressionMethod::Bzip2))?;
+        io::copy(&mut fs::File::open("Cargo.lock")?, &mut writer)?;
+
+        writer.finish()?;
+    }
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(hash_file(Path::new("Cargo.lock"))?, hash_file(&dir.join("Cargo.lock"))?);
+
+    // deferred sizes, the decoder must stop at the end of stream
+    let data = fs::read("Cargo.toml")?;
+    let buf = descriptor_zip("deferred.toml", &data, 12, crc32fast::hash(&data));
+
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-d")
+        .arg(dir)
+        .write_stdin(buf)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("deferred.toml"))?, data);
+
+    Ok(())
+}
+
+/// Lzma data in zip format, the `.lzma` header is replaced by version and properties.
+#[cfg(feature = "lzma")]
+fn lzma_compress(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    use xz2::stream::{ Stream, LzmaOptions };
+
+    let stream = Stream::new_lzma_encoder(&LzmaOptions::new_preset(6).unwrap()).unwrap();
+    let mut encoder = xz2::write::XzEncoder::new_stream(Vec::new(), stream);
+    encoder.write_all(data).unwrap();
+    let output = encoder.finish().unwrap();
+
+    let mut buf = vec![9, 20, 5, 0];
+    buf.extend_from_slice(&output[..5]);
+    buf.extend_from_slice(&output[13..]);
+    buf
+}
+
+#[test]
+#[cfg(feature = "lzma")]
+fn test_lzma() -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let data = fs::read("Cargo.lock")?;
+    let crc = crc32fast::hash(&data);
+    let size = data.len() as u32;
+    let comp_data = lzma_compress(&data);
+
+    // end of stream marker
+    let path = dir.join("test17.zip");
+    fs::write(&path, raw_zip("eos.lock", 1 << 1, 14, crc, size, &comp_data, &[]))?;
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("eos.lock"))?, data);
+
+    // sized by header
+    let path = dir.join("test18.zip");
+    fs::write(&path, raw_zip("sized.lock", 0, 14, crc, size, &comp_data, &[]))?;
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("sized.lock"))?, data);
+
+    // stream
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-d")
+        .arg(dir)
+        .write_stdin(raw_zip("stream.lock", 1 << 1, 14, crc, size, &comp_data, &[]))
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("stream.lock"))?, data);
+
+    // dictionary size beyond the memory limit
+    let mut comp_data2 = comp_data.clone();
+    comp_data2[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    let path = dir.join("test30.zip");
+    fs::write(&path, raw_zip("huge.lock", 1 << 1, 14, crc, size, &comp_data2, &[]))?;
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("exceeds the memory limit"));
+
+    // xz
+    let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+    encoder.write_all(&data)?;
+    let comp_data = encoder.finish()?;
+
+    let path = dir.join("test19.zip");
+    fs::write(&path, raw_zip("xz.lock", 0, 95, crc, size, &comp_data, &[]))?;
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("xz.lock"))?, data);
+
+    // deferred sizes, the decoder must stop at the end of stream
+    for (name, method) in [("deferred.lzma", 14), ("deferred.xz", 95)] {
+        Command::cargo_bin("unzrip")?
+            .arg("-")
+            .arg("-d")
+            .arg(dir)
+            .write_stdin(descriptor_zip(name, &data, method, crc))
+            .assert()
+            .success();
+
+        assert_eq!(fs::read(dir.join(name))?, data);
+    }
+
+    Ok(())
+}
+
+/// Pack bits starting from the least significant bit of each byte.
+#[cfg(any(feature = "deflate64", feature = "legacy-methods"))]
+struct BitWriter {
+    buf: Vec<u8>,
+    bits: u32,
+    len: u32
+}
+
+#[cfg(any(feature = "deflate64", feature = "legacy-methods"))]
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { buf: Vec::new(), bits: 0, len: 0 }
+    }
+
+    fn write(&mut self, value: u32, len: u32) {
+        for i in 0..len {
+            self.bits |= ((value >> i) & 1) << self.len;
+            self.len += 1;
+            if self.len == 8 {
+                self.buf.push(self.bits as u8);
+                self.bits = 0;
+                self.len = 0;
+            }
+        }
+    }
+
+    /// huffman codes are packed starting from the most significant bit
+    fn write_code(&mut self, code: u32, len: u32) {
+        let code = code.reverse_bits() >> (32 - len);
+        self.write(code, len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len != 0 {
+            self.buf.push(self.bits as u8);
+        }
+        self.buf
+    }
+}
+
+/// Deflate64 fixed huffman block of random literals,
+/// followed by a match that only deflate64 can encode.
+#[cfg(feature = "deflate64")]
+fn deflate64_zip(name: &str) -> (Vec<u8>, Vec<u8>) {
+    const LITERAL_LEN: usize = 40000;
+    const MATCH_LEN: usize = 50000;
+
+    let mut data = Vec::with_capacity(LITERAL_LEN + MATCH_LEN);
+    let mut state = 0x12345678u32;
+    for _ in 0..LITERAL_LEN {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        data.push((state >> 16) as u8);
+    }
+    for i in 0..MATCH_LEN {
+        data.push(data[i]);
+    }
+
+    let mut writer = BitWriter::new();
+
+    // final block, fixed huffman
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    for &b in &data[..LITERAL_LEN] {
+        match b {
+            0..=143 => writer.write_code(0x30 + u32::from(b), 8),
+            _ => writer.write_code(0x190 + u32::from(b) - 144, 9)
+        }
+    }
+
+    // length code 285 has 16 extra bits in deflate64
+    writer.write_code(0xc0 + (285 - 280), 8);
+    writer.write((MATCH_LEN - 3) as u32, 16);
+
+    // distance code 30 is only valid in deflate64
+    writer.write_code(30, 5);
+    writer.write((LITERAL_LEN - 32769) as u32, 14);
+
+    // end of block
+    writer.write_code(0, 7);
+
+    let zip = raw_zip(name, 0, 9, crc32fast::hash(&data), data.len() as u32, &writer.finish(), &[]);
+    (zip, data)
+}
+
+#[test]
+#[cfg(feature = "deflate64")]
+fn test_deflate64() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let path = dir.join("test20.zip");
+    let (zip, data) = deflate64_zip("deflate64.bin");
+    fs::write(&path, zip)?;
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("deflate64.bin"))?, data);
+
+    Ok(())
+}
+
+/// Shannon-Fano tree of `n` symbols with code lengths from 2 to `log2(n) + 1` bits,
+/// the symbols of the same length are scattered.
+#[cfg(feature = "legacy-methods")]
+struct ShannonFano {
+    lens: Vec<u32>,
+    codes: Vec<u32>
+}
+
+#[cfg(feature = "legacy-methods")]
+impl ShannonFano {
+    fn mixed(n: usize) -> ShannonFano {
+        // a complete tree, 1/4 + 1/8 + 2/16 + 4/n + (n - 8)/2n = 1
+        let bits = n.trailing_zeros();
+        let sorted = [2, 3, 4, 4, bits, bits, bits, bits].into_iter()
+            .chain(std::iter::repeat(bits + 1))
+            .take(n)
+            .collect::<Vec<_>>();
+        let lens = (0..n).map(|i| sorted[i * 37 % n]).collect::<Vec<_>>();
+
+        // canonical codes, the shorter codes and the smaller symbols first
+        let mut codes = vec![0; n];
+        let mut code = 0;
+        for bit_len in 1..=16 {
+            for (symbol, _) in lens.iter().enumerate().filter(|(_, &len)| len == bit_len) {
+                codes[symbol] = code;
+                code += 1;
+            }
+            code <<= 1;
+        }
+
+        ShannonFano { lens, codes }
+    }
+
+    /// Each byte is a run of up to 16 codes of the same bit length.
+    fn write_tree(&self, writer: &mut BitWriter) {
+        let mut runs = Vec::new();
+        for &len in &self.lens {
+            match runs.last_mut() {
+                Some((count, last)) if *last == len && *count < 16 => *count += 1,
+                _ => runs.push((1, len))
+            }
+        }
+
+        writer.write(runs.len() as u32 - 1, 8);
+        for (count, len) in runs {
+            writer.write(((count - 1) << 4) | (len - 1), 8);
+        }
+    }
+
+    /// The codes are written inverted.
+    fn write_symbol(&self, writer: &mut BitWriter, symbol: usize) {
+        let len = self.lens[symbol];
+        writer.write_code(!self.codes[symbol] & ((1 << len) - 1), len);
+    }
+}
+
+/// Imploded data of literals and matches.
+#[cfg(feature = "legacy-methods")]
+fn implode(gp_flag: u16, literals: &[u8], matches: &[(usize, usize)]) -> (Vec<u8>, Vec<u8>) {
+    let dist_low_bits = if gp_flag & (1 << 1) != 0 { 7 } else { 6 };
+    let literal_tree = gp_flag & (1 << 2) != 0;
+    let min_len = if literal_tree { 3 } else { 2 };
+
+    let mut data = literals.to_vec();
+    let mut writer = BitWriter::new();
+
+    let literal_codes = ShannonFano::mixed(256);
+    let length_codes = ShannonFano::mixed(64);
+    let dist_codes = ShannonFano::mixed(64);
+
+    if literal_tree {
+        literal_codes.write_tree(&mut writer);
+    }
+    length_codes.write_tree(&mut writer);
+    dist_codes.write_tree(&mut writer);
+
+    for &c in literals {
+        writer.write(1, 1);
+        if literal_tree {
+            literal_codes.write_symbol(&mut writer, c.into());
+        } else {
+            writer.write(u32::from(c), 8);
+        }
+    }
+
+    for &(dist, len) in matches {
+        for _ in 0..len {
+            data.push(data[data.len() - dist]);
+        }
+
+        let dist = dist - 1;
+        writer.write(0, 1);
+        writer.write((dist & ((1 << dist_low_bits) - 1)) as u32, dist_low_bits);
+        dist_codes.write_symbol(&mut writer, dist >> dist_low_bits);
+
+        let len = len - min_len;
+        if len >= 63 {
+            length_codes.write_symbol(&mut writer, 63);
+            writer.write((len - 63) as u32, 8);
+        } else {
+            length_codes.write_symbol(&mut writer, len);
+        }
+    }
+
+    (writer.finish(), data)
+}
+
+#[test]
+#[cfg(feature = "legacy-methods")]
+fn test_legacy_methods() -> anyhow::Result<()> {
+    use std::io::Read;
+
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let extract = |name: &str, gp_flag: u16, method: u16, comp_data: &[u8], data: &[u8]| -> anyhow::Result<()> {
+        let path = dir.join(format!("{}.zip", name));
+        let zip = raw_zip(name, gp_flag, method, crc32fast::hash(data), data.len() as u32, comp_data, &[]);
+
+        // the reference decoder, which is tested with the archives of PKZIP
+        let mut reference = zip_legacy::ZipArchive::new(io::Cursor::new(&zip))?;
+        let mut buf = Vec::new();
+        reference.by_index(0)?.read_to_end(&mut buf)?;
+        assert_eq!(buf, data, "{}", name);
+
+        fs::write(&path, zip)?;
+
+        Command::cargo_bin("unzrip")?
+            .arg(&path)
+            .arg("-d")
+            .arg(dir)
+            .assert()
+            .success();
+
+        assert_eq!(fs::read(dir.join(name))?, data, "{}", name);
+        Ok(())
+    };
+
+    // shrink, the lzw example in figure 5 of welch's paper
+    let comp_data = [0x61, 0xc4, 0x04, 0x1c, 0x23, 0xb0, 0x60, 0x98, 0x83, 0x08, 0xc3, 0x00];
+    extract("shrink", 0, 1, &comp_data, b"ababcbababaaaaaaa")?;
+
+    // reduce, 'a' and 'b' have follower sets, the match fits in all factors
+    let mut writer = BitWriter::new();
+    for c in (0..=255u8).rev() {
+        match c {
+            b'a' => {
+                writer.write(1, 6);
+                writer.write(u32::from(b'b'), 8);
+            },
+            b'b' => {
+                writer.write(2, 6);
+                writer.write(u32::from(b'x'), 8);
+                writer.write(u32::from(b'c'), 8);
+            },
+            _ => writer.write(0, 6)
+        }
+    }
+    writer.write(u32::from(b'a'), 8);
+    writer.write(0, 1); // follower 0 of 'a'
+    writer.write(0, 1);
+    writer.write(0, 1); // follower 1 of 'b'
+    writer.write(1, 1);
+    writer.write(0x90, 8); // DLE
+    writer.write(3, 8); // length 3 + 3
+    writer.write(2, 8); // distance 2 + 1
+    writer.write(0x90, 8); // DLE 0 is a literal DLE
+    writer.write(0, 8);
+    writer.write(u32::from(b'z'), 8);
+    let comp_data = writer.finish();
+    for factor in 1..=4 {
+        extract(&format!("reduce{}", factor), 0, 1 + factor, &comp_data, b"abcabcabc\x90z")?;
+    }
+
+    // implode with all combinations of dictionary and literal tree
+    let literals = (0..=255).collect::<Vec<u8>>();
+    let matches = [(3, 9), (200, 70), (256, 4)];
+    for gp_flag in [0, 1 << 1, 1 << 2, (1 << 1) | (1 << 2)] {
+        let (comp_data, data) = implode(gp_flag, &literals, &matches);
+        extract(&format!("implode{}", gp_flag), gp_flag, 6, &comp_data, &data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "zstd-sys")]
+fn zstd_compress(data: &[u8]) -> Vec<u8> {
+    zstd::encode_all(data, 0).unwrap()
+}
+
+#[cfg(feature = "zstd-rust")]
+fn zstd_compress(data: &[u8]) -> Vec<u8> {
+    ruzstd::encoding::compress_to_vec(data, ruzstd::encoding::CompressionLevel::Fastest)
+}
+
+#[test]
+#[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+fn test_zstd() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    let toml = fs::read("Cargo.toml")?;
+    let lock = fs::read("Cargo.lock")?;
+
+    // concatenated frames
+    let data = [toml.as_slice(), lock.as_slice()].concat();
+    let comp_data = [zstd_compress(&toml), zstd_compress(&lock)].concat();
+
+    let path = dir.join("test21.zip");
+    fs::write(&path, raw_zip("frames", 0, 93, crc32fast::hash(&data), data.len() as u32, &comp_data, &[]))?;
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("frames"))?, data);
+
+    // deferred sizes, the decoder must stop at the end of frame
+    let buf = descriptor_zip("deferred.toml", &toml, 93, crc32fast::hash(&toml));
+
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-d")
+        .arg(dir)
+        .write_stdin(buf)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("deferred.toml"))?, toml);
+
+    Ok(())
+}
+
+#[cfg(any(feature = "zstd-sys", feature = "zstd-rust"))]
+#[test]
+fn test_zstd_window_log_max() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let dir = dir.path();
+
+    // a frame with 256MiB window and a raw block
+    let data = b"hello large window";
+    let mut comp_data = vec![0x28, 0xb5, 0x2f, 0xfd, 0x00, 18 << 3];
+    let block_header = 1 | (data.len() as u32) << 3;
+    comp_data.extend_from_slice(&block_header.to_le_bytes()[..3]);
+    comp_data.extend_from_slice(data);
+
+    let path = dir.join("test22.zip");
+    fs::write(&path, raw_zip("window", 0, 93, crc32fast::hash(data), data.len() as u32, &comp_data, &[]))?;
+
+    let assert = Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .assert()
+        .failure();
+    assert!(assert.get_output().stderr.contains_str("exceeds the limit"));
+    assert!(!dir.join("window").exists());
+
+    Command::cargo_bin("unzrip")?
+        .arg(&path)
+        .arg("-d")
+        .arg(dir)
+        .arg("--zstd-window-log-max")
+        .arg("28")
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(dir.join("window"))?, data);
+
+    // the frame header is split at the end of stdin buffer, the window is 64MiB
+    let pad = vec![0; 8121];
+    comp_data[5] = 16 << 3;
+    let mut buf = Vec::new();
+    let entries = [
+        ("pad", 0u16, &pad[..], &pad[..]),
+        ("window", 93, &data[..], &comp_data[..])
+    ];
+    for (name, method, data, comp_data) in entries {
+        buf.extend_from_slice(b"PK\x03\x04");
+        buf.extend_from_slice(&20u16.to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(&method.to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(&0x21u16.to_le_bytes());
+        buf.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+        buf.extend_from_slice(&(comp_data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(name.as_bytes());
+        buf.extend_from_slice(comp_data);
+    }
+    assert_eq!(&buf[8190..][..4], b"\x28\xb5\x2f\xfd");
+
+    let dir2 = dir.join("2");
+    Command::cargo_bin("unzrip")?
+        .arg("-")
+        .arg("-d")
+        .arg(&dir2)
+        .arg("--zstd-window-log-max")
+        .arg("20")
+        .write_stdin(buf)
+        .assert()
+        .failure();
+    assert_ne!(fs::read(dir2.join("window")).ok().as_deref(), Some(&data[..]));
+
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! https://libzip.org/specifications/extrafld.txt
+
+use crate::Error;
+use crate::util::{ take, read_u8, read_u16, read_u32, read_u64 };
+
+
+pub mod id {
+    pub const ZIP64: u16              = 0x0001;
+    pub const NTFS: u16               = 0x000a;
+    pub const EXTENDED_TIMESTAMP: u16 = 0x5455;
+    pub const UNICODE_COMMENT: u16    = 0x6375;
+    pub const UNICODE_PATH: u16       = 0x7075;
+    pub const INFOZIP_UNIX: u16       = 0x7875;
+    pub const AES: u16                = 0x9901;
+}
+
+type Field<'a> = (u16, &'a [u8]);
+
+/// Iterator over the `(header id, data)` pairs of an extra field.
+#[derive(Clone)]
+pub struct ExtraFields<'a> {
+    buf: &'a [u8]
+}
+
+impl ExtraFields<'_> {
+    pub fn new(buf: &[u8]) -> ExtraFields<'_> {
+        ExtraFields { buf }
+    }
+}
+
+impl<'a> Iterator for ExtraFields<'a> {
+    type Item = Result<Field<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        fn parse(input: &[u8]) -> Result<(&[u8], Field<'_>), Error> {
+            let (input, id) = read_u16(input)?;
+            let (input, size) = read_u16(input)?;
+            let (input, data) = take(input, size.into())?;
+            Ok((input, (id, data)))
+        }
+
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        match parse(self.buf) {
+            Ok((input, field)) => {
+                self.buf = input;
+                Some(Ok(field))
+            },
+            Err(err) => {
+                self.buf = &[];
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// ZIP64 extended information (0x0001)
+///
+/// The fields are only present when the corresponding header field
+/// is set to `0xFFFF` or `0xFFFFFFFF`, and always in the order
+/// uncompressed size, compressed size, lfh offset, disk number,
+/// so they have to be resolved one by one.
+#[derive(Debug, Default)]
+pub struct Zip64<'a> {
+    buf: &'a [u8]
+}
+
+impl Zip64<'_> {
+    pub fn parse(data: &[u8]) -> Result<Zip64<'_>, Error> {
+        Ok(Zip64 { buf: data })
+    }
+
+    pub fn resolve_u32(&mut self, value: u32) -> Result<u64, Error> {
+        if value == u32::MAX && !self.buf.is_empty() {
+            let (input, value) = read_u64(self.buf).map_err(|_| Error::BadZip64)?;
+            self.buf = input;
+            Ok(value)
+        } else {
+            Ok(value.into())
+        }
+    }
+
+    pub fn resolve_u16(&mut self, value: u16) -> Result<u32, Error> {
+        if value == u16::MAX && !self.buf.is_empty() {
+            let (input, value) = read_u32(self.buf).map_err(|_| Error::BadZip64)?;
+            self.buf = input;
+            Ok(value)
+        } else {
+            Ok(value.into())
+        }
+    }
+}
+
+/// NTFS (0x000a), times are in 100ns intervals since 1601-01-01
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct Ntfs {
+    pub mtime: u64,
+    pub atime: u64,
+    pub ctime: u64
+}
+
+impl Ntfs {
+    pub fn parse(data: &[u8]) -> Result<Ntfs, Error> {
+        const TIMES_TAG: u16 = 0x0001;
+        const TIMES_SIZE: u16 = 24;
+
+        let (mut input, _reserved) = read_u32(data)?;
+
+        while !input.is_empty() {
+            let (rest, tag) = read_u16(input)?;
+            let (rest, size) = read_u16(rest)?;
+            let (rest, attr) = take(rest, size.into())?;
+
+            if tag == TIMES_TAG && size == TIMES_SIZE {
+                let (attr, mtime) = read_u64(attr)?;
+                let (attr, atime) = read_u64(attr)?;
+                let (_attr, ctime) = read_u64(attr)?;
+                return Ok(Ntfs { mtime, atime, ctime });
+            }
+
+            input = rest;
+        }
+
+        Err(Error::BadExtra)
+    }
+}
+
+/// Extended timestamp (0x5455), times are unix timestamps
+///
+/// The central directory version only carries the modification time,
+/// even if the flags announce more.
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct ExtendedTimestamp {
+    pub flags: u8,
+    pub mtime: Option<i32>,
+    pub atime: Option<i32>,
+    pub ctime: Option<i32>
+}
+
+impl ExtendedTimestamp {
+    pub fn parse(data: &[u8]) -> Result<ExtendedTimestamp, Error> {
+        fn read_time(input: &[u8], flags: u8, bit: u8) -> Result<(&[u8], Option<i32>), Error> {
+            if flags & bit != 0 && !input.is_empty() {
+                let (input, time) = read_u32(input)?;
+                Ok((input, Some(time as i32)))
+            } else {
+                Ok((input, None))
+            }
+        }
+
+        let (input, flags) = read_u8(data)?;
+        let (input, mtime) = read_time(input, flags, 1 << 0)?;
+        let (input, atime) = read_time(input, flags, 1 << 1)?;
+        let (_input, ctime) = read_time(input, flags, 1 << 2)?;
+
+        Ok(ExtendedTimestamp { flags, mtime, atime, ctime })
+    }
+}
+
+/// Info-ZIP Unix, type 3 (0x7875)
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct InfoZipUnix {
+    pub uid: u64,
+    pub gid: u64
+}
+
+impl InfoZipUnix {
+    pub fn parse(data: &[u8]) -> Result<InfoZipUnix, Error> {
+        fn read_id(input: &[u8]) -> Result<(&[u8], u64), Error> {
+            let (input, size) = read_u8(input)?;
+            if usize::from(size) > std::mem::size_of::<u64>() {
+                return Err(Error::BadExtra);
+            }
+            let (input, id) = take(input, size.into())?;
+            let id = id.iter()
+                .rev()
+                .fold(0, |acc, &b| (acc << 8) | u64::from(b));
+            Ok((input, id))
+        }
+
+        let (input, version) = read_u8(data)?;
+        if version != 1 {
+            return Err(Error::BadExtra);
+        }
+
+        let (input, uid) = read_id(input)?;
+        let (_input, gid) = read_id(input)?;
+
+        Ok(InfoZipUnix { uid, gid })
+    }
+}
+
+/// Info-ZIP Unicode path (0x7075) and comment (0x6375)
+///
+/// `crc32` is the checksum of the field in the header,
+/// the value should be ignored if it does not match.
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct Unicode<'a> {
+    pub crc32: u32,
+    pub value: &'a [u8]
+}
+
+impl Unicode<'_> {
+    pub fn parse(data: &[u8]) -> Result<Unicode<'_>, Error> {
+        let (input, version) = read_u8(data)?;
+        if version != 1 {
+            return Err(Error::BadExtra);
+        }
+
+        let (value, crc32) = read_u32(input)?;
+
+        Ok(Unicode { crc32, value })
+    }
+}
+
+/// WinZip AES (0x9901)
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct Aes {
+    pub version: u16,
+    pub strength: u8,
+    pub method: u16
+}
+
+impl Aes {
+    pub fn parse(data: &[u8]) -> Result<Aes, Error> {
+        const VENDOR_ID: &[u8; 2] = b"AE";
+
+        let (input, version) = read_u16(data)?;
+        let (input, vendor_id) = take(input, VENDOR_ID.len())?;
+        let (input, strength) = read_u8(input)?;
+        let (_input, method) = read_u16(input)?;
+
+        if vendor_id != VENDOR_ID || !matches!(strength, 1..=3) {
+            return Err(Error::BadExtra);
+        }
+
+        Ok(Aes { version, strength, method })
+    }
+}
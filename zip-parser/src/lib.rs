@@ -1,16 +1,34 @@
 //! https://www.hanshq.net/zip.html#zip
 
 mod util;
-
+pub mod extra;
+pub mod stream;
+pub mod scan;
+pub mod write;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
 use thiserror::Error;
 use memchr::memmem::rfind;
-use util::{ Eof, take, read_u16, read_u32 };
+use util::{ Eof, take, read_u16, read_u32, read_u64 };
+use extra::{ ExtraFields, Zip64 };
 
 
 pub mod compress {
-    pub const STORE: u16   = 0;
-    pub const DEFLATE: u16 = 8;
-    pub const ZSTD: u16    = 93;
+    pub const STORE: u16     = 0;
+    pub const SHRINK: u16    = 1;
+    pub const REDUCE1: u16   = 2;
+    pub const REDUCE2: u16   = 3;
+    pub const REDUCE3: u16   = 4;
+    pub const REDUCE4: u16   = 5;
+    pub const IMPLODE: u16   = 6;
+    pub const DEFLATE: u16   = 8;
+    pub const DEFLATE64: u16 = 9;
+    pub const BZIP2: u16     = 12;
+    pub const LZMA: u16      = 14;
+    pub const ZSTD: u16      = 93;
+    pub const XZ: u16        = 95;
 }
 
 pub mod system {
@@ -18,15 +36,33 @@ pub mod system {
     pub const UNIX: u16 = 3;
 }
 
+pub mod gp_flag {
+    pub const ENCRYPTED: u16       = 1 << 0;
+    /// lzma data is terminated by an end of stream marker
+    pub const LZMA_EOS: u16        = 1 << 1;
+    pub const DATA_DESCRIPTOR: u16 = 1 << 3;
+    pub const UTF8: u16            = 1 << 11;
+}
+
+const LFH_SIGNATURE: &[u8; 4]     = &[b'P', b'K', 3, 4];
+const DD_SIGNATURE: &[u8; 4]      = &[b'P', b'K', 7, 8];
+const CFH_SIGNATURE: &[u8; 4]     = &[b'P', b'K', 1, 2];
+const EOCDR64_SIGNATURE: &[u8; 4] = &[b'P', b'K', 6, 6];
+const EOCDL64_SIGNATURE: &[u8; 4] = &[b'P', b'K', 6, 7];
+const EOCDR_SIGNATURE: &[u8; 4]   = &[b'P', b'K', 5, 6];
+
+/// central file header without name, extra field and comment
+const CFH_MIN_SIZE: usize = 46;
+
 #[non_exhaustive]
 #[derive(Debug)]
 pub struct EocdRecord<'a> {
-    pub disk_nbr: u16,
-    pub cd_start_disk: u16,
-    pub disk_cd_entries: u16,
-    pub cd_entries: u16,
-    pub cd_size: u32,
-    pub cd_offset: u32,
+    pub disk_nbr: u32,
+    pub cd_start_disk: u32,
+    pub disk_cd_entries: u64,
+    pub cd_entries: u64,
+    pub cd_size: u64,
+    pub cd_offset: u64,
     pub comment: &'a [u8]
 }
 
@@ -36,14 +72,26 @@ pub enum Error {
     Eof,
     #[error("bad eocdr magic number")]
     BadEocdr,
+    #[error("bad zip64 eocdr magic number")]
+    BadEocdr64,
     #[error("bad cfh magic number")]
     BadCfh,
     #[error("bad lfh magic number")]
     BadLfh,
+    #[error("bad zip64 extended information")]
+    BadZip64,
+    #[error("bad extra field")]
+    BadExtra,
     #[error("not supported")]
     Unsupported,
     #[error("offset overflow")]
-    OffsetOverflow
+    OffsetOverflow,
+    #[error("too large to write without zip64")]
+    TooLarge,
+    #[error("overlapped entry: {0:?}")]
+    Overlap(Overlap),
+    #[error(transparent)]
+    Io(#[from] std::io::Error)
 }
 
 impl From<Eof> for Error {
@@ -54,22 +102,22 @@ impl From<Eof> for Error {
 }
 
 impl EocdRecord<'_> {
-    fn find(buf: &[u8]) -> Result<EocdRecord<'_>, Error> {
-        const EOCDR_SIGNATURE: &[u8; 4] = &[b'P', b'K', 5, 6];
+    /// `base` is the offset of `buf` in the archive.
+    ///
+    /// Also returns the offset in `buf` where the central directory ends.
+    fn find(buf: &[u8], base: u64) -> Result<(usize, EocdRecord<'_>), Error> {
         const MAX_BACK_OFFSET: usize = 1024 * 128;
 
-        let eocdr_buf = {
-            let max_back_buf = buf.len()
-                .checked_sub(MAX_BACK_OFFSET)
-                .map(|pos| &buf[pos..])
-                .unwrap_or(buf);
+        let eocdr_offset = {
+            let max_back_offset = buf.len().saturating_sub(MAX_BACK_OFFSET);
+            let max_back_buf = &buf[max_back_offset..];
 
             let eocdr_offset = rfind(max_back_buf, EOCDR_SIGNATURE)
                 .ok_or(Error::BadEocdr)?;
-            &max_back_buf[eocdr_offset..]
+            max_back_offset + eocdr_offset
         };
 
-        let input = eocdr_buf;
+        let input = &buf[eocdr_offset..];
         let (input, _) = take(input, EOCDR_SIGNATURE.len())?;
         let (input, disk_nbr) = read_u16(input)?;
         let (input, cd_start_disk) = read_u16(input)?;
@@ -80,7 +128,79 @@ impl EocdRecord<'_> {
         let (input, comment_len) = read_u16(input)?;
         let (_input, comment) = take(input, comment_len.into())?;
 
-        Ok(EocdRecord {
+        let eocdr = EocdRecord {
+            disk_nbr: disk_nbr.into(),
+            cd_start_disk: cd_start_disk.into(),
+            disk_cd_entries: disk_cd_entries.into(),
+            cd_entries: cd_entries.into(),
+            cd_size: cd_size.into(),
+            cd_offset: cd_offset.into(),
+            comment
+        };
+
+        match Eocd64Locator::find(buf, eocdr_offset)? {
+            Some(locator) => locator.read(buf, base, eocdr.comment),
+            None => Ok((eocdr_offset, eocdr))
+        }
+    }
+}
+
+struct Eocd64Locator {
+    offset: usize,
+    eocdr64_offset: u64
+}
+
+impl Eocd64Locator {
+    const SIZE: usize = 20;
+
+    fn find(buf: &[u8], eocdr_offset: usize) -> Result<Option<Eocd64Locator>, Error> {
+        let offset = match eocdr_offset.checked_sub(Eocd64Locator::SIZE) {
+            Some(offset) => offset,
+            None => return Ok(None)
+        };
+        let input = &buf[offset..eocdr_offset];
+
+        let (input, expect_sig) = take(input, EOCDL64_SIGNATURE.len())?;
+        if expect_sig != EOCDL64_SIGNATURE {
+            return Ok(None);
+        }
+
+        let (input, _eocdr64_disk) = read_u32(input)?;
+        let (input, eocdr64_offset) = read_u64(input)?;
+        let (_input, _total_disks) = read_u32(input)?;
+
+        Ok(Some(Eocd64Locator { offset, eocdr64_offset }))
+    }
+
+    fn read<'a>(&self, buf: &'a [u8], base: u64, comment: &'a [u8])
+        -> Result<(usize, EocdRecord<'a>), Error>
+    {
+        const EOCDR64_SIZE: usize = 56;
+
+        // the recorded offset is wrong if data is prepended to the archive,
+        // so also try right before the locator, assuming no extensible data.
+        let recorded_offset = self.eocdr64_offset.checked_sub(base)
+            .and_then(|offset| offset.try_into().ok());
+        let offset = recorded_offset.into_iter()
+            .chain(self.offset.checked_sub(EOCDR64_SIZE))
+            .find(|&offset| buf.get(offset..)
+                .is_some_and(|input| input.starts_with(EOCDR64_SIGNATURE)))
+            .ok_or(Error::BadEocdr64)?;
+
+        let input = &buf[offset..];
+        let (input, _) = take(input, EOCDR64_SIGNATURE.len())?;
+
+        let (input, _record_size) = read_u64(input)?;
+        let (input, _made_by_ver) = read_u16(input)?;
+        let (input, _extract_ver) = read_u16(input)?;
+        let (input, disk_nbr) = read_u32(input)?;
+        let (input, cd_start_disk) = read_u32(input)?;
+        let (input, disk_cd_entries) = read_u64(input)?;
+        let (input, cd_entries) = read_u64(input)?;
+        let (input, cd_size) = read_u64(input)?;
+        let (_input, cd_offset) = read_u64(input)?;
+
+        let eocdr = EocdRecord {
             disk_nbr,
             cd_start_disk,
             disk_cd_entries,
@@ -88,10 +208,20 @@ impl EocdRecord<'_> {
             cd_size,
             cd_offset,
             comment
-        })
+        };
+
+        Ok((offset, eocdr))
     }
 }
 
+fn find_zip64(extra: &[u8]) -> Result<Zip64<'_>, Error> {
+    ExtraFields::new(extra)
+        .filter_map(Result::ok)
+        .find(|(id, _)| *id == extra::id::ZIP64)
+        .map(|(_, data)| Zip64::parse(data))
+        .unwrap_or_else(|| Ok(Zip64::default()))
+}
+
 #[non_exhaustive]
 #[derive(Debug)]
 pub struct CentralFileHeader<'a> {
@@ -102,12 +232,12 @@ pub struct CentralFileHeader<'a> {
     pub mod_time: u16,
     pub mod_date: u16,
     pub crc32: u32,
-    pub comp_size: u32,
-    pub uncomp_size: u32,
-    pub disk_nbr_start: u16,
+    pub comp_size: u64,
+    pub uncomp_size: u64,
+    pub disk_nbr_start: u32,
     pub int_attrs: u16,
     pub ext_attrs: u32,
-    pub lfh_offset: u32,
+    pub lfh_offset: u64,
     pub name: &'a [u8],
     pub extra: &'a [u8],
     pub comment: &'a [u8]
@@ -115,8 +245,6 @@ pub struct CentralFileHeader<'a> {
 
 impl CentralFileHeader<'_> {
     fn parse(input: &[u8]) -> Result<(&[u8], CentralFileHeader<'_>), Error> {
-        const CFH_SIGNATURE: &[u8; 4] = &[b'P', b'K', 1, 2];
-
         let (input, expect_sig) = take(input, CFH_SIGNATURE.len())?;
         if expect_sig != CFH_SIGNATURE {
             return Err(Error::BadCfh);
@@ -142,6 +270,12 @@ impl CentralFileHeader<'_> {
         let (input, extra) = take(input, extra_len.into())?;
         let (input, comment) = take(input, comment_len.into())?;
 
+        let mut zip64 = find_zip64(extra)?;
+        let uncomp_size = zip64.resolve_u32(uncomp_size)?;
+        let comp_size = zip64.resolve_u32(comp_size)?;
+        let lfh_offset = zip64.resolve_u32(lfh_offset)?;
+        let disk_nbr_start = zip64.resolve_u16(disk_nbr_start)?;
+
         let header = CentralFileHeader {
             made_by_ver,
             extract_ver,
@@ -165,6 +299,18 @@ impl CentralFileHeader<'_> {
     }
 }
 
+impl<'a> CentralFileHeader<'a> {
+    pub fn extra_fields(&self) -> ExtraFields<'a> {
+        ExtraFields::new(self.extra)
+    }
+
+    fn parse_at(buf: &'a [u8], offset: usize) -> Result<CentralFileHeader<'a>, Error> {
+        let input = buf.get(offset..).ok_or(Error::OffsetOverflow)?;
+        let (_, cfh) = CentralFileHeader::parse(input)?;
+        Ok(cfh)
+    }
+}
+
 #[non_exhaustive]
 #[derive(Debug)]
 pub struct LocalFileHeader<'a> {
@@ -174,16 +320,17 @@ pub struct LocalFileHeader<'a> {
     pub mod_time: u16,
     pub mod_date: u16,
     pub crc32: u32,
-    pub comp_size: u32,
-    pub uncomp_size: u32,
+    pub comp_size: u64,
+    pub uncomp_size: u64,
     pub name: &'a [u8],
-    pub extra: &'a [u8]
+    pub extra: &'a [u8],
+    /// Only available after [`ZipArchive::read`],
+    /// if the sizes and crc are deferred to a data descriptor.
+    pub descriptor: Option<DataDescriptor>
 }
 
 impl LocalFileHeader<'_> {
     fn parse(input: &[u8]) -> Result<(&[u8], LocalFileHeader<'_>), Error> {
-        const LFH_SIGNATURE: &[u8; 4] = &[b'P', b'K', 3, 4];
-
         let (input, expect_sig) = take(input, LFH_SIGNATURE.len())?;
         if expect_sig != LFH_SIGNATURE {
             return Err(Error::BadLfh);
@@ -202,6 +349,10 @@ impl LocalFileHeader<'_> {
         let (input, name) = take(input, name_len.into())?;
         let (input, extra) = take(input, extra_len.into())?;
 
+        let mut zip64 = find_zip64(extra)?;
+        let uncomp_size = zip64.resolve_u32(uncomp_size)?;
+        let comp_size = zip64.resolve_u32(comp_size)?;
+
         let header = LocalFileHeader {
             extract_ver,
             gp_flag,
@@ -212,21 +363,80 @@ impl LocalFileHeader<'_> {
             comp_size,
             uncomp_size,
             name,
-            extra
+            extra,
+            descriptor: None
         };
 
         Ok((input, header))
     }
 }
 
+impl<'a> LocalFileHeader<'a> {
+    pub fn extra_fields(&self) -> ExtraFields<'a> {
+        ExtraFields::new(self.extra)
+    }
+
+    fn is_zip64(&self) -> bool {
+        self.extra_fields()
+            .filter_map(Result::ok)
+            .any(|(id, _)| id == extra::id::ZIP64)
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct DataDescriptor {
+    pub crc32: u32,
+    pub comp_size: u64,
+    pub uncomp_size: u64
+}
+
+impl DataDescriptor {
+    fn parse(input: &[u8], zip64: bool) -> Result<(&[u8], DataDescriptor), Error> {
+        // the signature is optional
+        let input = input.strip_prefix(DD_SIGNATURE).unwrap_or(input);
+
+        DataDescriptor::parse_body(input, zip64)
+    }
+
+    fn parse_body(input: &[u8], zip64: bool) -> Result<(&[u8], DataDescriptor), Error> {
+        let (input, crc32) = read_u32(input)?;
+        let (input, comp_size, uncomp_size) = if zip64 {
+            let (input, comp_size) = read_u64(input)?;
+            let (input, uncomp_size) = read_u64(input)?;
+            (input, comp_size, uncomp_size)
+        } else {
+            let (input, comp_size) = read_u32(input)?;
+            let (input, uncomp_size) = read_u32(input)?;
+            (input, comp_size.into(), uncomp_size.into())
+        };
+
+        let descriptor = DataDescriptor {
+            crc32,
+            comp_size,
+            uncomp_size
+        };
+
+        Ok((input, descriptor))
+    }
+}
+
 pub struct ZipArchive<'a> {
     buf: &'a [u8],
-    eocdr: EocdRecord<'a>
+    eocdr: EocdRecord<'a>,
+    prefix_len: u64,
+    index: Option<ZipIndex<'a>>
+}
+
+struct ZipIndex<'a> {
+    /// offsets of central file headers, relative to the central directory
+    offsets: Vec<usize>,
+    names: HashMap<&'a [u8], usize>
 }
 
 impl ZipArchive<'_> {
     pub fn parse(buf: &[u8]) -> Result<ZipArchive<'_>, Error> {
-        let eocdr = EocdRecord::find(buf)?;
+        let (cd_end, eocdr) = EocdRecord::find(buf, 0)?;
 
         if eocdr.disk_nbr != 0
             || eocdr.cd_start_disk != 0
@@ -235,59 +445,327 @@ impl ZipArchive<'_> {
             return Err(Error::Unsupported);
         }
 
-        Ok(ZipArchive { buf, eocdr })
+        // data may be prepended to the archive, such as self-extracting executable,
+        // then all offsets are shifted by the length of the prefix.
+        // if the sizes are inconsistent, trust the offsets.
+        let prefix_len = (cd_end as u64)
+            .checked_sub(eocdr.cd_offset)
+            .and_then(|len| len.checked_sub(eocdr.cd_size))
+            .unwrap_or(0);
+
+        Ok(ZipArchive { buf, eocdr, prefix_len, index: None })
+    }
+
+    /// Scan for local file headers without the central directory,
+    /// see [`scan::LocalEntries`].
+    pub fn scan_local_headers(buf: &[u8]) -> scan::LocalEntries<'_> {
+        scan::LocalEntries::new(buf, 0)
+    }
+
+    /// Scan for local file headers starting at `offset`.
+    pub fn scan_local_headers_from(buf: &[u8], offset: u64) -> scan::LocalEntries<'_> {
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        scan::LocalEntries::new(buf, offset)
     }
 
     pub fn eocdr(&self) -> &EocdRecord<'_> {
         &self.eocdr
     }
 
+    /// Length of the data prepended to the archive,
+    /// such as the stub of self-extracting executable.
+    pub fn prefix_len(&self) -> u64 {
+        self.prefix_len
+    }
+
+    fn offset(&self, offset: u64) -> Result<usize, Error> {
+        offset.checked_add(self.prefix_len)
+            .and_then(|offset| offset.try_into().ok())
+            .ok_or(Error::OffsetOverflow)
+    }
+
+    /// The number of entries recorded in the end of central directory record.
+    pub fn len(&self) -> usize {
+        usize::try_from(self.eocdr.cd_entries).unwrap_or(usize::MAX)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.eocdr.cd_entries == 0
+    }
+
     pub fn entries(&self) -> Result<ZipEntries<'_>, Error> {
-        let offset: usize = self.eocdr.cd_offset.try_into()
-            .map_err(|_| Error::OffsetOverflow)?;
-        let buf = self.buf.get(offset..)
-            .ok_or(Error::OffsetOverflow)?;
-        let count = self.eocdr.cd_entries;
+        let buf = self.central_directory()?;
 
-        Ok(ZipEntries { buf, count })
+        Ok(match &self.index {
+            Some(index) => ZipEntries::indexed(buf, &index.offsets),
+            None => ZipEntries::new(buf, self.eocdr.cd_entries)
+        })
+    }
+
+    /// Find the entry by raw name bytes, the first one wins if the name is duplicated.
+    ///
+    /// This is a linear search unless [`ZipArchive::build_index`] was called.
+    pub fn by_name(&self, name: &[u8]) -> Result<Option<CentralFileHeader<'_>>, Error> {
+        match &self.index {
+            Some(index) => match index.names.get(name) {
+                Some(&i) => self.by_index(i),
+                None => Ok(None)
+            },
+            None => {
+                for cfh in self.entries()? {
+                    let cfh = cfh?;
+                    if cfh.name == name {
+                        return Ok(Some(cfh));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Get the entry by its position in the central directory.
+    ///
+    /// This is a linear search unless [`ZipArchive::build_index`] was called.
+    pub fn by_index(&self, index: usize) -> Result<Option<CentralFileHeader<'_>>, Error> {
+        match &self.index {
+            Some(zip_index) => match zip_index.offsets.get(index) {
+                Some(&offset) => {
+                    let buf = self.central_directory()?;
+                    CentralFileHeader::parse_at(buf, offset).map(Some)
+                },
+                None => Ok(None)
+            },
+            None => self.entries()?.nth(index).transpose()
+        }
     }
 
     pub fn read<'a>(&'a self, cfh: &CentralFileHeader) -> Result<(LocalFileHeader<'a>, &'a [u8]), Error> {
-        let offset: usize = cfh.lfh_offset.try_into()
-            .map_err(|_| Error::OffsetOverflow)?;
+        let (_, lfh, buf) = self.read_entry(cfh)?;
+        Ok((lfh, buf))
+    }
+
+    /// Also returns the input after the entry.
+    fn read_entry<'a>(&'a self, cfh: &CentralFileHeader)
+        -> Result<(&'a [u8], LocalFileHeader<'a>, &'a [u8]), Error>
+    {
+        let offset = self.offset(cfh.lfh_offset)?;
         let buf = self.buf.get(offset..)
             .ok_or(Error::OffsetOverflow)?;
 
-        let (input, lfh) = LocalFileHeader::parse(buf)?;
+        let (input, mut lfh) = LocalFileHeader::parse(buf)?;
 
         let size = cfh.comp_size.try_into()
             .map_err(|_| Error::OffsetOverflow)?;
-        let (_, buf) = take(input, size)?;
+        let (input, buf) = take(input, size)?;
+
+        let input = if lfh.gp_flag & gp_flag::DATA_DESCRIPTOR != 0 {
+            let zip64 = lfh.is_zip64()
+                || cfh.comp_size >= u32::MAX.into()
+                || cfh.uncomp_size >= u32::MAX.into();
+            let (input, descriptor) = DataDescriptor::parse(input, zip64)?;
+            lfh.descriptor = Some(descriptor);
+            input
+        } else {
+            input
+        };
 
-        Ok((lfh, buf))
+        Ok((input, lfh, buf))
+    }
+
+    /// Check that the `[lfh_offset, end of data)` span of every entry
+    /// is disjoint and before the central directory.
+    ///
+    /// Many entries sharing the same data is the construction of
+    /// https://www.bamsoftware.com/hacks/zipbomb/
+    pub fn check_overlap(&self) -> Result<(), Error> {
+        match self.find_overlaps()?.into_iter().next() {
+            Some(overlap) => Err(Error::Overlap(overlap)),
+            None => Ok(())
+        }
+    }
+
+    /// Like [`ZipArchive::check_overlap`], but report all overlaps.
+    pub fn find_overlaps(&self) -> Result<Vec<Overlap>, Error> {
+        let mut spans = Vec::new();
+        for cfh in self.entries()? {
+            let cfh = cfh?;
+            let (input, ..) = self.read_entry(&cfh)?;
+            let end = (self.buf.len() - input.len()) as u64 - self.prefix_len;
+            spans.push((cfh.lfh_offset, end));
+        }
+        spans.sort_unstable();
+
+        let cd_offset = self.eocdr.cd_offset;
+        let mut overlaps = Vec::new();
+        let mut last: Option<(u64, u64)> = None;
+
+        for &(offset, end) in spans.iter() {
+            if end > cd_offset {
+                overlaps.push(Overlap::CentralDirectory { offset });
+            }
+
+            match last {
+                Some((other, last_end)) if offset < last_end => {
+                    overlaps.push(Overlap::Entry { offset, other });
+
+                    if end > last_end {
+                        last = Some((offset, end));
+                    }
+                },
+                _ => last = Some((offset, end))
+            }
+        }
+
+        Ok(overlaps)
+    }
+}
+
+impl<'a> ZipArchive<'a> {
+    /// Parse the whole central directory once,
+    /// so that [`ZipArchive::by_name`] and [`ZipArchive::by_index`] are constant time.
+    pub fn build_index(&mut self) -> Result<(), Error> {
+        let buf = self.central_directory()?;
+        let offsets = find_offsets(buf, self.eocdr.cd_entries)?;
+
+        let mut names = HashMap::with_capacity(offsets.len());
+        for (i, &offset) in offsets.iter().enumerate() {
+            let cfh = CentralFileHeader::parse_at(buf, offset)?;
+            names.entry(cfh.name).or_insert(i);
+        }
+
+        self.index = Some(ZipIndex { offsets, names });
+
+        Ok(())
+    }
+
+    fn central_directory(&self) -> Result<&'a [u8], Error> {
+        let offset = self.offset(self.eocdr.cd_offset)?;
+        self.buf.get(offset..)
+            .ok_or(Error::OffsetOverflow)
     }
 }
 
+/// Entries are identified by their lfh offset.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Overlap {
+    /// the entry data overlaps with another entry
+    Entry {
+        offset: u64,
+        other: u64
+    },
+    /// the entry data reaches into the central directory
+    CentralDirectory {
+        offset: u64
+    }
+}
+
+/// Iterator over the central file headers.
+///
+/// It yields the recorded number of entries, but no more than the central directory can hold,
+/// once an entry fails to parse, all the remaining entries are the error.
 pub struct ZipEntries<'a> {
     buf: &'a [u8],
-    count: u16
+    state: EntriesState<'a>
+}
+
+enum EntriesState<'a> {
+    /// parse forward from the start of `buf`
+    Linear {
+        count: u64
+    },
+    /// parse at the remaining offsets in `buf`
+    Indexed {
+        offsets: Cow<'a, [usize]>,
+        range: Range<usize>
+    }
+}
+
+impl<'a> ZipEntries<'a> {
+    fn new(buf: &'a [u8], count: u64) -> ZipEntries<'a> {
+        // the recorded count is untrusted, one more than fits is enough to yield the error
+        let max = (buf.len() / CFH_MIN_SIZE) as u64 + 1;
+        let count = count.min(max);
+        ZipEntries { buf, state: EntriesState::Linear { count } }
+    }
+
+    fn indexed(buf: &'a [u8], offsets: &'a [usize]) -> ZipEntries<'a> {
+        let range = 0..offsets.len();
+        let offsets = Cow::Borrowed(offsets);
+        ZipEntries { buf, state: EntriesState::Indexed { offsets, range } }
+    }
 }
 
 impl<'a> Iterator for ZipEntries<'a> {
     type Item = Result<CentralFileHeader<'a>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let new_count = self.count.checked_sub(1)?;
+        match &mut self.state {
+            EntriesState::Linear { count } => {
+                *count = count.checked_sub(1)?;
+
+                match CentralFileHeader::parse(self.buf) {
+                    Ok((input, cfh)) => {
+                        self.buf = input;
+                        Some(Ok(cfh))
+                    },
+                    Err(err) => Some(Err(err))
+                }
+            },
+            EntriesState::Indexed { offsets, range } => {
+                let i = range.next()?;
+                Some(CentralFileHeader::parse_at(self.buf, offsets[i]))
+            }
+        }
+    }
 
-        let input = self.buf;
-        let (input, cfh) = match CentralFileHeader::parse(input) {
-            Ok(output) => output,
-            Err(err) => return Some(Err(err))
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match &self.state {
+            EntriesState::Linear { count } => usize::try_from(*count).unwrap_or(usize::MAX),
+            EntriesState::Indexed { range, .. } => range.len()
         };
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for ZipEntries<'_> {}
+
+impl DoubleEndedIterator for ZipEntries<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        // without index, find the offsets of the remaining entries first
+        if let EntriesState::Linear { count } = self.state {
+            match find_offsets(self.buf, count) {
+                Ok(offsets) => {
+                    let range = 0..offsets.len();
+                    let offsets = Cow::Owned(offsets);
+                    self.state = EntriesState::Indexed { offsets, range };
+                },
+                Err(err) => {
+                    self.state = EntriesState::Linear { count: count.checked_sub(1)? };
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        match &mut self.state {
+            EntriesState::Indexed { offsets, range } => {
+                let i = range.next_back()?;
+                Some(CentralFileHeader::parse_at(self.buf, offsets[i]))
+            },
+            EntriesState::Linear { .. } => None
+        }
+    }
+}
 
-        self.buf = input;
-        self.count = new_count;
+fn find_offsets(buf: &[u8], count: u64) -> Result<Vec<usize>, Error> {
+    let mut offsets = Vec::new();
+    let mut input = buf;
 
-        Some(Ok(cfh))
+    for _ in 0..count {
+        offsets.push(buf.len() - input.len());
+        let (rest, _) = CentralFileHeader::parse(input)?;
+        input = rest;
     }
+
+    Ok(offsets)
 }
@@ -0,0 +1,121 @@
+//! Linear scan of local file headers,
+//! for archives whose central directory is truncated or corrupted.
+
+use memchr::memmem;
+use crate::{ Error, LocalFileHeader, DataDescriptor, gp_flag, LFH_SIGNATURE, DD_SIGNATURE };
+use crate::util::take;
+
+
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct LocalEntry<'a> {
+    pub offset: u64,
+    pub header: LocalFileHeader<'a>,
+    pub data: &'a [u8]
+}
+
+impl LocalEntry<'_> {
+    /// The crc32 from data descriptor if present, otherwise from local file header.
+    pub fn crc32(&self) -> u32 {
+        match self.header.descriptor.as_ref() {
+            Some(descriptor) => descriptor.crc32,
+            None => self.header.crc32
+        }
+    }
+
+    /// The uncompressed size from data descriptor if present, otherwise from local file header.
+    pub fn uncomp_size(&self) -> u64 {
+        match self.header.descriptor.as_ref() {
+            Some(descriptor) => descriptor.uncomp_size,
+            None => self.header.uncomp_size
+        }
+    }
+}
+
+/// Iterator over the structurally valid local file headers.
+///
+/// Candidates that fail to parse are skipped,
+/// the data of a valid entry is never scanned for signatures, since it may be a nested archive.
+///
+/// An entry with corrupted size hides the entries after it,
+/// rescan from its offset if it fails to extract.
+pub struct LocalEntries<'a> {
+    buf: &'a [u8],
+    pos: usize
+}
+
+impl LocalEntries<'_> {
+    pub(crate) fn new(buf: &[u8], pos: usize) -> LocalEntries<'_> {
+        LocalEntries { buf, pos: pos.min(buf.len()) }
+    }
+}
+
+impl<'a> Iterator for LocalEntries<'a> {
+    type Item = LocalEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(found) = memmem::find(&self.buf[self.pos..], LFH_SIGNATURE) {
+            let offset = self.pos + found;
+
+            match parse_entry(self.buf, offset) {
+                Ok((end, entry)) => {
+                    self.pos = end;
+                    return Some(entry);
+                },
+                Err(_) => self.pos = offset + LFH_SIGNATURE.len()
+            }
+        }
+
+        self.pos = self.buf.len();
+        None
+    }
+}
+
+fn parse_entry(buf: &[u8], offset: usize) -> Result<(usize, LocalEntry<'_>), Error> {
+    let (input, mut header) = LocalFileHeader::parse(&buf[offset..])?;
+    let deferred = header.gp_flag & gp_flag::DATA_DESCRIPTOR != 0;
+    let zip64 = header.is_zip64();
+
+    let (rest, data) = if deferred && header.comp_size == 0 {
+        let (rest, data, descriptor) = find_descriptor(input, zip64)
+            .ok_or(Error::Eof)?;
+        header.descriptor = Some(descriptor);
+        (rest, data)
+    } else {
+        let size = header.comp_size.try_into()
+            .map_err(|_| Error::OffsetOverflow)?;
+        let (rest, data) = take(input, size)?;
+
+        if deferred {
+            let (rest, descriptor) = DataDescriptor::parse(rest, zip64)?;
+            header.descriptor = Some(descriptor);
+            (rest, data)
+        } else {
+            (rest, data)
+        }
+    };
+
+    let entry = LocalEntry {
+        offset: offset as u64,
+        header,
+        data
+    };
+
+    Ok((buf.len() - rest.len(), entry))
+}
+
+/// Find the data descriptor whose compressed size matches its position.
+///
+/// The size is unknown, so only descriptors with signature can be found.
+fn find_descriptor(input: &[u8], zip64: bool) -> Option<(&[u8], &[u8], DataDescriptor)> {
+    memmem::find_iter(input, DD_SIGNATURE)
+        .find_map(|pos| {
+            let (rest, descriptor) = DataDescriptor::parse(&input[pos..], zip64).ok()?;
+
+            if descriptor.comp_size == pos as u64 {
+                Some((rest, &input[..pos], descriptor))
+            } else {
+                None
+            }
+        })
+}
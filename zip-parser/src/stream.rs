@@ -0,0 +1,238 @@
+//! Forward-only reading of local file headers,
+//! for input that cannot be seeked, such as stdin or pipe.
+//!
+//! If an entry defers its sizes to a data descriptor,
+//! the compressed size is unknown until the end of the data,
+//! so the decoder must detect the end of stream by itself
+//! and must not read beyond it.
+
+use std::cmp;
+use std::io::{ self, Read, BufRead };
+use crate::{
+    Error, EocdRecord, LocalFileHeader, DataDescriptor, ZipEntries,
+    compress, gp_flag,
+    LFH_SIGNATURE, DD_SIGNATURE, CFH_SIGNATURE, EOCDR64_SIGNATURE, EOCDR_SIGNATURE
+};
+
+
+pub struct ZipStream<R> {
+    reader: R,
+    header: Vec<u8>,
+    state: State
+}
+
+struct State {
+    data: Data,
+    /// `Some(zip64)` if a data descriptor follows the data
+    descriptor: Option<bool>,
+    /// offset of the next unread byte
+    offset: u64,
+    /// offset of the data of current entry
+    data_offset: u64,
+    end: bool
+}
+
+enum Data {
+    None,
+    Sized(u64),
+    Unsized
+}
+
+impl<R: BufRead> ZipStream<R> {
+    pub fn new(reader: R) -> ZipStream<R> {
+        ZipStream {
+            reader,
+            header: Vec::new(),
+            state: State {
+                data: Data::None,
+                descriptor: None,
+                offset: 0,
+                data_offset: 0,
+                end: false
+            }
+        }
+    }
+
+    /// Read the next local file header,
+    /// or `None` if the central directory is reached.
+    ///
+    /// The unread data of the previous entry is skipped.
+    pub fn next_entry(&mut self) -> Result<Option<StreamEntry<'_, R>>, Error> {
+        const FIXED_LEN: usize = 30;
+
+        let ZipStream { reader, header, state } = self;
+
+        if state.end {
+            return Ok(None);
+        }
+
+        finish_entry(reader, state)?;
+
+        header.clear();
+        header.resize(LFH_SIGNATURE.len(), 0);
+        reader.read_exact(header)?;
+
+        match header.as_slice() {
+            sig if sig == LFH_SIGNATURE => (),
+            sig if sig == CFH_SIGNATURE
+                || sig == EOCDR64_SIGNATURE
+                || sig == EOCDR_SIGNATURE =>
+            {
+                state.end = true;
+                return Ok(None);
+            },
+            _ => return Err(Error::BadLfh)
+        }
+
+        header.resize(FIXED_LEN, 0);
+        reader.read_exact(&mut header[LFH_SIGNATURE.len()..])?;
+
+        let name_len = u16::from_le_bytes([header[26], header[27]]);
+        let extra_len = u16::from_le_bytes([header[28], header[29]]);
+        header.resize(FIXED_LEN + usize::from(name_len) + usize::from(extra_len), 0);
+        reader.read_exact(&mut header[FIXED_LEN..])?;
+        state.offset += header.len() as u64;
+        state.data_offset = state.offset;
+
+        let (_, lfh) = LocalFileHeader::parse(header)?;
+
+        if lfh.gp_flag & gp_flag::DATA_DESCRIPTOR != 0 {
+            state.data = if lfh.comp_size != 0 {
+                Data::Sized(lfh.comp_size)
+            } else if lfh.method != compress::STORE {
+                Data::Unsized
+            } else {
+                // stored data has no end of stream
+                return Err(Error::Unsupported);
+            };
+            state.descriptor = Some(lfh.is_zip64());
+        } else {
+            state.data = Data::Sized(lfh.comp_size);
+        }
+
+        Ok(Some(StreamEntry { header: lfh, reader, state }))
+    }
+
+    /// Skip the remaining entries and read the central directory.
+    pub fn into_central_directory(mut self) -> Result<CentralDirectory, Error> {
+        while self.next_entry()?.is_some() {}
+
+        let offset = self.state.offset;
+        let mut buf = self.header;
+        self.reader.read_to_end(&mut buf)?;
+
+        Ok(CentralDirectory { buf, offset })
+    }
+}
+
+pub struct StreamEntry<'a, R> {
+    pub header: LocalFileHeader<'a>,
+    reader: &'a mut R,
+    state: &'a mut State
+}
+
+impl<R: BufRead> StreamEntry<'_, R> {
+    /// Whether the compressed size is unknown until the end of stream.
+    pub fn is_unsized(&self) -> bool {
+        matches!(self.state.data, Data::Unsized)
+    }
+
+    /// The number of data bytes read so far,
+    /// which is the compressed size once an unsized entry is decoded to the end.
+    pub fn read_len(&self) -> u64 {
+        self.state.offset - self.state.data_offset
+    }
+
+    /// Skip the unread data and read the data descriptor, if any.
+    pub fn finish(self) -> Result<Option<DataDescriptor>, Error> {
+        finish_entry(self.reader, self.state)
+    }
+}
+
+impl<R: BufRead> Read for StreamEntry<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let input = self.fill_buf()?;
+        let n = cmp::min(input.len(), buf.len());
+        buf[..n].copy_from_slice(&input[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for StreamEntry<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.reader.fill_buf()?;
+
+        match self.state.data {
+            Data::None => Ok(&[]),
+            Data::Sized(n) => {
+                let n = usize::try_from(n).unwrap_or(usize::MAX);
+                Ok(&buf[..cmp::min(n, buf.len())])
+            },
+            Data::Unsized => Ok(buf)
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt);
+        self.state.offset += amt as u64;
+
+        if let Data::Sized(n) = &mut self.state.data {
+            *n -= amt as u64;
+        }
+    }
+}
+
+fn finish_entry<R: BufRead>(reader: &mut R, state: &mut State)
+    -> Result<Option<DataDescriptor>, Error>
+{
+    if let Data::Sized(n) = state.data {
+        let skipped = io::copy(&mut reader.take(n), &mut io::sink())?;
+        state.offset += skipped;
+
+        if skipped != n {
+            return Err(Error::Eof);
+        }
+    }
+
+    state.data = Data::None;
+
+    let zip64 = match state.descriptor.take() {
+        Some(zip64) => zip64,
+        None => return Ok(None)
+    };
+
+    let mut buf = [0; 4 + 8 + 8];
+    let len = if zip64 { 20 } else { 12 };
+
+    // the signature is optional
+    reader.read_exact(&mut buf[..4])?;
+    if &buf[..4] == DD_SIGNATURE {
+        reader.read_exact(&mut buf[..4])?;
+        state.offset += 4;
+    }
+    reader.read_exact(&mut buf[4..len])?;
+    state.offset += len as u64;
+
+    let (_, descriptor) = DataDescriptor::parse_body(&buf[..len], zip64)?;
+
+    Ok(Some(descriptor))
+}
+
+/// Central directory read from the end of a stream.
+pub struct CentralDirectory {
+    buf: Vec<u8>,
+    offset: u64
+}
+
+impl CentralDirectory {
+    pub fn eocdr(&self) -> Result<EocdRecord<'_>, Error> {
+        EocdRecord::find(&self.buf, self.offset).map(|(_, eocdr)| eocdr)
+    }
+
+    pub fn entries(&self) -> Result<ZipEntries<'_>, Error> {
+        let count = self.eocdr()?.cd_entries;
+
+        Ok(ZipEntries::new(&self.buf, count))
+    }
+}
@@ -28,3 +28,18 @@ pub fn read_u32(input: &[u8]) -> Result<(&[u8], u32), Eof> {
     let output = u32::from_le_bytes(buf);
     Ok((input, output))
 }
+
+#[inline]
+pub fn read_u64(input: &[u8]) -> Result<(&[u8], u64), Eof> {
+    let mut buf = [0; 8];
+    let (input, output) = take(input, buf.len())?;
+    buf.copy_from_slice(output);
+    let output = u64::from_le_bytes(buf);
+    Ok((input, output))
+}
+
+#[inline]
+pub fn read_u8(input: &[u8]) -> Result<(&[u8], u8), Eof> {
+    let (input, output) = take(input, 1)?;
+    Ok((input, output[0]))
+}
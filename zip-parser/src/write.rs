@@ -0,0 +1,492 @@
+//! Writing of archives.
+//!
+//! The writer does not compress, the data of [`ZipWriter::start_file`] is stored,
+//! and the already compressed data can be added by [`ZipWriter::add_raw_file`].
+//!
+//! The output is never seeked, so the sizes and crc of a streamed entry
+//! are deferred to a data descriptor, such entry is stored
+//! and cannot be read by [`crate::stream::ZipStream`].
+
+use std::io::{ self, Write };
+use crate::{
+    Error, EocdRecord, CentralFileHeader, LocalFileHeader, DataDescriptor,
+    compress, system, gp_flag, extra,
+    LFH_SIGNATURE, DD_SIGNATURE, CFH_SIGNATURE, EOCDR64_SIGNATURE, EOCDL64_SIGNATURE, EOCDR_SIGNATURE
+};
+
+
+const VERSION: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+#[derive(Debug, Clone, Default)]
+pub struct FileOptions {
+    mod_time: u16,
+    mod_date: u16,
+    unix_mode: Option<u32>,
+    large_file: bool,
+    comment: Vec<u8>
+}
+
+impl FileOptions {
+    /// MS-DOS time and date, default is 1980-00-00 00:00:00.
+    pub fn mod_time(mut self, time: u16, date: u16) -> FileOptions {
+        self.mod_time = time;
+        self.mod_date = date;
+        self
+    }
+
+    /// Unix file mode, including the file type bits.
+    pub fn unix_mode(mut self, mode: u32) -> FileOptions {
+        self.unix_mode = Some(mode);
+        self
+    }
+
+    /// Write zip64 local header for streamed entry,
+    /// it must be set if the entry may reach 4GiB.
+    pub fn large_file(mut self, large_file: bool) -> FileOptions {
+        self.large_file = large_file;
+        self
+    }
+
+    pub fn comment(mut self, comment: &[u8]) -> FileOptions {
+        self.comment = comment.into();
+        self
+    }
+}
+
+pub struct ZipWriter<W: Write> {
+    writer: CountWriter<W>,
+    entries: Vec<Entry>,
+    current: Option<Current>
+}
+
+struct Entry {
+    gp_flag: u16,
+    method: u16,
+    mod_time: u16,
+    mod_date: u16,
+    crc32: u32,
+    comp_size: u64,
+    uncomp_size: u64,
+    unix_mode: Option<u32>,
+    lfh_offset: u64,
+    name: Vec<u8>,
+    comment: Vec<u8>
+}
+
+struct Current {
+    entry: Entry,
+    hasher: crc32fast::Hasher,
+    data_offset: u64,
+    large_file: bool
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(writer: W) -> ZipWriter<W> {
+        ZipWriter {
+            writer: CountWriter { inner: writer, count: 0 },
+            entries: Vec::new(),
+            current: None
+        }
+    }
+
+    /// Start a stored entry, the data is written through [`Write`].
+    pub fn start_file(&mut self, name: &[u8], options: &FileOptions) -> Result<(), Error> {
+        self.finish_file()?;
+
+        let mut entry = Entry::new(name, options, compress::STORE, self.writer.count);
+        entry.gp_flag |= gp_flag::DATA_DESCRIPTOR;
+
+        let (sentinel, zip64) = if options.large_file {
+            (u32::MAX.into(), zip64_extra(&[0, 0]))
+        } else {
+            (0, Vec::new())
+        };
+
+        let lfh = LocalFileHeader {
+            extract_ver: if options.large_file { VERSION_ZIP64 } else { VERSION },
+            gp_flag: entry.gp_flag,
+            method: entry.method,
+            mod_time: entry.mod_time,
+            mod_date: entry.mod_date,
+            crc32: 0,
+            comp_size: sentinel,
+            uncomp_size: sentinel,
+            name: &entry.name,
+            extra: &zip64,
+            descriptor: None
+        };
+        lfh.write(&mut self.writer)?;
+
+        self.current = Some(Current {
+            entry,
+            hasher: crc32fast::Hasher::new(),
+            data_offset: self.writer.count,
+            large_file: options.large_file
+        });
+
+        Ok(())
+    }
+
+    /// Add an entry whose data is already compressed by `method`.
+    pub fn add_raw_file(
+        &mut self,
+        name: &[u8],
+        options: &FileOptions,
+        method: u16,
+        crc32: u32,
+        uncomp_size: u64,
+        data: &[u8]
+    ) -> Result<(), Error> {
+        self.finish_file()?;
+
+        let mut entry = Entry::new(name, options, method, self.writer.count);
+        entry.crc32 = crc32;
+        entry.comp_size = data.len() as u64;
+        entry.uncomp_size = uncomp_size;
+
+        // both sizes must be present in the zip64 extra field of local header
+        let large_file = entry.comp_size >= u32::MAX.into()
+            || entry.uncomp_size >= u32::MAX.into();
+        let (comp_size, uncomp_size, zip64) = if large_file {
+            let zip64 = zip64_extra(&[entry.uncomp_size, entry.comp_size]);
+            (u32::MAX.into(), u32::MAX.into(), zip64)
+        } else {
+            (entry.comp_size, entry.uncomp_size, Vec::new())
+        };
+
+        let lfh = LocalFileHeader {
+            extract_ver: if large_file { VERSION_ZIP64 } else { VERSION },
+            gp_flag: entry.gp_flag,
+            method: entry.method,
+            mod_time: entry.mod_time,
+            mod_date: entry.mod_date,
+            crc32: entry.crc32,
+            comp_size,
+            uncomp_size,
+            name: &entry.name,
+            extra: &zip64,
+            descriptor: None
+        };
+        lfh.write(&mut self.writer)?;
+        self.writer.write_all(data)?;
+
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Add a directory entry, `/` is appended to the name if missing.
+    pub fn add_directory(&mut self, name: &[u8], options: &FileOptions) -> Result<(), Error> {
+        const S_IFDIR: u32 = 0o040000;
+
+        let mut name = name.to_vec();
+        if !name.ends_with(b"/") {
+            name.push(b'/');
+        }
+
+        let mut options = options.clone();
+        if let Some(mode) = options.unix_mode {
+            options.unix_mode = Some(S_IFDIR | (mode & 0o7777));
+        }
+
+        self.add_raw_file(&name, &options, compress::STORE, 0, 0, &[])
+    }
+
+    fn finish_file(&mut self) -> Result<(), Error> {
+        let Current { mut entry, hasher, data_offset, large_file } = match self.current.take() {
+            Some(current) => current,
+            None => return Ok(())
+        };
+
+        let comp_size = self.writer.count - data_offset;
+        if !large_file && comp_size >= u32::MAX.into() {
+            return Err(Error::TooLarge);
+        }
+
+        entry.crc32 = hasher.finalize();
+        entry.comp_size = comp_size;
+        entry.uncomp_size = comp_size;
+
+        let descriptor = DataDescriptor {
+            crc32: entry.crc32,
+            comp_size: entry.comp_size,
+            uncomp_size: entry.uncomp_size
+        };
+        descriptor.write(&mut self.writer, large_file)?;
+
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Write the central directory, and return the inner writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.finish_file()?;
+
+        let cd_offset = self.writer.count;
+        for entry in self.entries.iter() {
+            entry.write_cfh(&mut self.writer)?;
+        }
+        let cd_end = self.writer.count;
+
+        let eocdr = EocdRecord {
+            disk_nbr: 0,
+            cd_start_disk: 0,
+            disk_cd_entries: self.entries.len() as u64,
+            cd_entries: self.entries.len() as u64,
+            cd_size: cd_end - cd_offset,
+            cd_offset,
+            comment: &[]
+        };
+
+        if eocdr.cd_entries >= u16::MAX.into()
+            || eocdr.cd_size >= u32::MAX.into()
+            || eocdr.cd_offset >= u32::MAX.into()
+        {
+            eocdr.write_zip64(&mut self.writer)?;
+        }
+        eocdr.write(&mut self.writer)?;
+
+        self.writer.flush()?;
+
+        Ok(self.writer.inner)
+    }
+}
+
+impl<W: Write> Write for ZipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let current = self.current.as_mut()
+            .ok_or_else(|| io::Error::other("no file started"))?;
+        let n = self.writer.write(buf)?;
+        current.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Entry {
+    fn new(name: &[u8], options: &FileOptions, method: u16, lfh_offset: u64) -> Entry {
+        let gp_flag = if !name.is_ascii() && std::str::from_utf8(name).is_ok() {
+            gp_flag::UTF8
+        } else {
+            0
+        };
+
+        Entry {
+            gp_flag,
+            method,
+            mod_time: options.mod_time,
+            mod_date: options.mod_date,
+            crc32: 0,
+            comp_size: 0,
+            uncomp_size: 0,
+            unix_mode: options.unix_mode,
+            lfh_offset,
+            name: name.into(),
+            comment: options.comment.clone()
+        }
+    }
+
+    fn write_cfh<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        // only the overflowed fields, in this order
+        let zip64 = [self.uncomp_size, self.comp_size, self.lfh_offset]
+            .into_iter()
+            .filter(|&value| value >= u32::MAX.into())
+            .collect::<Vec<_>>();
+        let is_zip64 = !zip64.is_empty();
+        let zip64 = zip64_extra(&zip64);
+
+        const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+        let dos_attrs = if self.name.ends_with(b"/") { FILE_ATTRIBUTE_DIRECTORY } else { 0 };
+        let (system, ext_attrs) = match self.unix_mode {
+            Some(mode) => (system::UNIX, (mode << 16) | dos_attrs),
+            None => (system::DOS, dos_attrs)
+        };
+        let version = if is_zip64 { VERSION_ZIP64 } else { VERSION };
+
+        let cfh = CentralFileHeader {
+            made_by_ver: (system << 8) | VERSION_ZIP64,
+            extract_ver: version,
+            gp_flag: self.gp_flag,
+            method: self.method,
+            mod_time: self.mod_time,
+            mod_date: self.mod_date,
+            crc32: self.crc32,
+            comp_size: self.comp_size,
+            uncomp_size: self.uncomp_size,
+            disk_nbr_start: 0,
+            int_attrs: 0,
+            ext_attrs,
+            lfh_offset: self.lfh_offset,
+            name: &self.name,
+            extra: &zip64,
+            comment: &self.comment
+        };
+        cfh.write(writer)
+    }
+}
+
+struct CountWriter<W> {
+    inner: W,
+    count: u64
+}
+
+impl<W: Write> Write for CountWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn zip64_extra(values: &[u64]) -> Vec<u8> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+
+    let mut buf = Vec::with_capacity(4 + values.len() * 8);
+    buf.extend_from_slice(&extra::id::ZIP64.to_le_bytes());
+    buf.extend_from_slice(&(values.len() as u16 * 8).to_le_bytes());
+    for value in values {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+    buf
+}
+
+/// The field is set to the maximum value if it is moved to zip64 extra field.
+fn clamp_u32(value: u64) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+fn clamp_u16(value: u64) -> u16 {
+    u16::try_from(value).unwrap_or(u16::MAX)
+}
+
+fn len_u16(buf: &[u8]) -> Result<u16, Error> {
+    u16::try_from(buf.len()).map_err(|_| Error::TooLarge)
+}
+
+impl LocalFileHeader<'_> {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(30 + self.name.len() + self.extra.len());
+        buf.extend_from_slice(LFH_SIGNATURE);
+        buf.extend_from_slice(&self.extract_ver.to_le_bytes());
+        buf.extend_from_slice(&self.gp_flag.to_le_bytes());
+        buf.extend_from_slice(&self.method.to_le_bytes());
+        buf.extend_from_slice(&self.mod_time.to_le_bytes());
+        buf.extend_from_slice(&self.mod_date.to_le_bytes());
+        buf.extend_from_slice(&self.crc32.to_le_bytes());
+        buf.extend_from_slice(&clamp_u32(self.comp_size).to_le_bytes());
+        buf.extend_from_slice(&clamp_u32(self.uncomp_size).to_le_bytes());
+        buf.extend_from_slice(&len_u16(self.name)?.to_le_bytes());
+        buf.extend_from_slice(&len_u16(self.extra)?.to_le_bytes());
+        buf.extend_from_slice(self.name);
+        buf.extend_from_slice(self.extra);
+
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+impl DataDescriptor {
+    fn write<W: Write>(&self, writer: &mut W, zip64: bool) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(4 + 4 + 8 + 8);
+        buf.extend_from_slice(DD_SIGNATURE);
+        buf.extend_from_slice(&self.crc32.to_le_bytes());
+        if zip64 {
+            buf.extend_from_slice(&self.comp_size.to_le_bytes());
+            buf.extend_from_slice(&self.uncomp_size.to_le_bytes());
+        } else {
+            buf.extend_from_slice(&clamp_u32(self.comp_size).to_le_bytes());
+            buf.extend_from_slice(&clamp_u32(self.uncomp_size).to_le_bytes());
+        }
+
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+impl CentralFileHeader<'_> {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(46 + self.name.len() + self.extra.len() + self.comment.len());
+        buf.extend_from_slice(CFH_SIGNATURE);
+        buf.extend_from_slice(&self.made_by_ver.to_le_bytes());
+        buf.extend_from_slice(&self.extract_ver.to_le_bytes());
+        buf.extend_from_slice(&self.gp_flag.to_le_bytes());
+        buf.extend_from_slice(&self.method.to_le_bytes());
+        buf.extend_from_slice(&self.mod_time.to_le_bytes());
+        buf.extend_from_slice(&self.mod_date.to_le_bytes());
+        buf.extend_from_slice(&self.crc32.to_le_bytes());
+        buf.extend_from_slice(&clamp_u32(self.comp_size).to_le_bytes());
+        buf.extend_from_slice(&clamp_u32(self.uncomp_size).to_le_bytes());
+        buf.extend_from_slice(&len_u16(self.name)?.to_le_bytes());
+        buf.extend_from_slice(&len_u16(self.extra)?.to_le_bytes());
+        buf.extend_from_slice(&len_u16(self.comment)?.to_le_bytes());
+        buf.extend_from_slice(&clamp_u16(self.disk_nbr_start.into()).to_le_bytes());
+        buf.extend_from_slice(&self.int_attrs.to_le_bytes());
+        buf.extend_from_slice(&self.ext_attrs.to_le_bytes());
+        buf.extend_from_slice(&clamp_u32(self.lfh_offset).to_le_bytes());
+        buf.extend_from_slice(self.name);
+        buf.extend_from_slice(self.extra);
+        buf.extend_from_slice(self.comment);
+
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+impl EocdRecord<'_> {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(22 + self.comment.len());
+        buf.extend_from_slice(EOCDR_SIGNATURE);
+        buf.extend_from_slice(&clamp_u16(self.disk_nbr.into()).to_le_bytes());
+        buf.extend_from_slice(&clamp_u16(self.cd_start_disk.into()).to_le_bytes());
+        buf.extend_from_slice(&clamp_u16(self.disk_cd_entries).to_le_bytes());
+        buf.extend_from_slice(&clamp_u16(self.cd_entries).to_le_bytes());
+        buf.extend_from_slice(&clamp_u32(self.cd_size).to_le_bytes());
+        buf.extend_from_slice(&clamp_u32(self.cd_offset).to_le_bytes());
+        buf.extend_from_slice(&len_u16(self.comment)?.to_le_bytes());
+        buf.extend_from_slice(self.comment);
+
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Write zip64 eocdr and its locator, right after the central directory.
+    fn write_zip64<W: Write>(&self, writer: &mut CountWriter<W>) -> Result<(), Error> {
+        const RECORD_SIZE: u64 = 56;
+
+        let eocdr64_offset = writer.count;
+
+        let mut buf = Vec::with_capacity(RECORD_SIZE as usize + 20);
+        buf.extend_from_slice(EOCDR64_SIGNATURE);
+        // the size of remaining record
+        buf.extend_from_slice(&(RECORD_SIZE - 12).to_le_bytes());
+        buf.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        buf.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        buf.extend_from_slice(&self.disk_nbr.to_le_bytes());
+        buf.extend_from_slice(&self.cd_start_disk.to_le_bytes());
+        buf.extend_from_slice(&self.disk_cd_entries.to_le_bytes());
+        buf.extend_from_slice(&self.cd_entries.to_le_bytes());
+        buf.extend_from_slice(&self.cd_size.to_le_bytes());
+        buf.extend_from_slice(&self.cd_offset.to_le_bytes());
+
+        buf.extend_from_slice(EOCDL64_SIGNATURE);
+        buf.extend_from_slice(&self.cd_start_disk.to_le_bytes());
+        buf.extend_from_slice(&eocdr64_offset.to_le_bytes());
+        // total number of disks
+        buf.extend_from_slice(&1u32.to_le_bytes());
+
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}